@@ -0,0 +1,512 @@
+//throughput and allocations of parser::parse_with against the parser it replaced, which read
+//the whole input into a vector of tokens, reversed it and popped the tokens off the end.
+//run with `cargo bench`, a name as argument only runs the documents that contain it. events is
+//parse_events with a visitor that keeps nothing, the scanning without building the JsonContext.
+//skip is PullParser::skip_value over the whole document, which checks it but keeps nothing
+use fksjson::parser::{self, JsonVisitor, ParseOptions};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fmt::Write;
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+//counts every allocation, reallocations included
+struct Counting;
+
+static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const MIN_TIME: Duration = Duration::from_millis(500);
+
+//the old design, cut down to what valid documents need
+mod legacy {
+    use fksjson::{JsonContext, JsonValue};
+
+    #[derive(PartialEq)]
+    enum TokenType {
+        OBrk,
+        CBrk,
+        OSBrk,
+        CSBrk,
+        Colon,
+        Comma,
+        True,
+        False,
+        Null,
+        Number,
+        String,
+    }
+
+    struct Token {
+        value: String,
+        typ: TokenType,
+    }
+
+    fn lex(str: &str) -> Option<Vec<Token>> {
+        let mut tokens = Vec::new();
+        let mut chars = str.chars().peekable();
+
+        while let Some(ch) = chars.next() {
+            let typ = match ch {
+                ' ' | '\t' | '\r' | '\n' => continue,
+                '{' => TokenType::OBrk,
+                '}' => TokenType::CBrk,
+                '[' => TokenType::OSBrk,
+                ']' => TokenType::CSBrk,
+                ':' => TokenType::Colon,
+                ',' => TokenType::Comma,
+                't' | 'f' | 'n' => {
+                    let word = match ch {
+                        't' => "true",
+                        'f' => "false",
+                        _ => "null",
+                    };
+                    for expect in word.chars().skip(1) {
+                        if chars.next() != Some(expect) {
+                            return None;
+                        }
+                    }
+
+                    let typ = match ch {
+                        't' => TokenType::True,
+                        'f' => TokenType::False,
+                        _ => TokenType::Null,
+                    };
+                    tokens.push(Token {
+                        value: word.to_string(),
+                        typ,
+                    });
+                    continue;
+                }
+                '\"' => {
+                    let mut value = String::new();
+                    loop {
+                        match chars.next()? {
+                            '\"' => break,
+                            '\\' => match chars.next()? {
+                                'n' => value.push('\n'),
+                                't' => value.push('\t'),
+                                'r' => value.push('\r'),
+                                'b' => value.push('\x08'),
+                                'f' => value.push('\x0C'),
+                                'u' => {
+                                    let mut code = 0;
+                                    for _ in 0..4 {
+                                        code = (code << 4) | chars.next()?.to_digit(16)?;
+                                    }
+                                    value.push(char::from_u32(code)?);
+                                }
+                                esc => value.push(esc),
+                            },
+                            c => value.push(c),
+                        }
+                    }
+
+                    tokens.push(Token {
+                        value,
+                        typ: TokenType::String,
+                    });
+                    continue;
+                }
+                _ if ch.is_ascii_digit() || ch == '-' => {
+                    let mut value = ch.to_string();
+                    while let Some(&c) = chars.peek() {
+                        if !(c.is_alphanumeric() || c == '.' || c == '+' || c == '-') {
+                            break;
+                        }
+                        value.push(c);
+                        chars.next();
+                    }
+
+                    tokens.push(Token {
+                        value,
+                        typ: TokenType::Number,
+                    });
+                    continue;
+                }
+                _ => return None,
+            };
+
+            tokens.push(Token {
+                value: ch.to_string(),
+                typ,
+            });
+        }
+
+        Some(tokens)
+    }
+
+    fn scalar(cxt: &mut JsonContext, tkn: Token) -> Option<JsonValue> {
+        Some(match tkn.typ {
+            TokenType::True => cxt.val_bool(true),
+            TokenType::False => cxt.val_bool(false),
+            TokenType::Null => cxt.val_null(),
+            TokenType::Number => match tkn.value.parse::<i64>() {
+                Ok(int) => cxt.val_int(int),
+                Err(_) => cxt.val_float(tkn.value.parse().ok()?),
+            },
+            TokenType::String => cxt.val_string(tkn.value),
+            _ => return None,
+        })
+    }
+
+    //the value the token starts, objects and arrays are read to their end
+    fn value(cxt: &mut JsonContext, tkn: Token, tokens: &mut Vec<Token>) -> Option<JsonValue> {
+        match tkn.typ {
+            TokenType::OBrk => {
+                let (obj, obj_id) = cxt.val_obj();
+                parse_obj(cxt, obj_id, tokens)?;
+                Some(obj)
+            }
+            TokenType::OSBrk => {
+                let (arr, arr_id) = cxt.val_array();
+                parse_arr(cxt, arr_id, tokens)?;
+                Some(arr)
+            }
+            _ => scalar(cxt, tkn),
+        }
+    }
+
+    fn parse_obj(cxt: &mut JsonContext, obj_id: u64, tokens: &mut Vec<Token>) -> Option<()> {
+        loop {
+            let tkn = tokens.pop()?;
+            match tkn.typ {
+                TokenType::CBrk => return Some(()),
+                TokenType::String => {}
+                _ => return None,
+            }
+
+            if tokens.pop()?.typ != TokenType::Colon {
+                return None;
+            }
+
+            let value_tkn = tokens.pop()?;
+            let val = value(cxt, value_tkn, tokens)?;
+            cxt.set_val(obj_id, tkn.value, val);
+
+            match tokens.pop()?.typ {
+                TokenType::Comma => {}
+                TokenType::CBrk => return Some(()),
+                _ => return None,
+            }
+        }
+    }
+
+    fn parse_arr(cxt: &mut JsonContext, arr_id: u64, tokens: &mut Vec<Token>) -> Option<()> {
+        loop {
+            let tkn = tokens.pop()?;
+            if tkn.typ == TokenType::CSBrk {
+                return Some(());
+            }
+
+            let val = value(cxt, tkn, tokens)?;
+            cxt.array_push(arr_id, val);
+
+            match tokens.pop()?.typ {
+                TokenType::Comma => {}
+                TokenType::CSBrk => return Some(()),
+                _ => return None,
+            }
+        }
+    }
+
+    pub fn parse(str: &str) -> Option<(JsonContext, u64)> {
+        let mut tokens = lex(str)?;
+        tokens.reverse();
+
+        let token = tokens.pop()?;
+        let (mut cxt, root_id) = match token.typ {
+            TokenType::OBrk => JsonContext::new(true),
+            TokenType::OSBrk => JsonContext::new(false),
+            _ => return None,
+        };
+
+        match token.typ {
+            TokenType::OBrk => parse_obj(&mut cxt, root_id, &mut tokens)?,
+            _ => parse_arr(&mut cxt, root_id, &mut tokens)?,
+        }
+
+        tokens.is_empty().then_some((cxt, root_id))
+    }
+}
+
+//a small xorshift, the documents are the same on every run
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, max: u64) -> u64 {
+        self.next() % max
+    }
+}
+
+const WORDS: &[&str] = &[
+    "alpha", "beta", "gamma", "delta", "sensor", "gateway", "north", "south", "primary", "backup",
+    "eu-west", "us-east", "ok", "degraded", "offline", "café", "zürich", "東京",
+];
+
+//an array of records like a service would log them
+fn telemetry(count: usize) -> String {
+    let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+    let mut doc = String::from("[");
+
+    for i in 0..count {
+        if i > 0 {
+            doc.push(',');
+        }
+
+        let word = WORDS[rng.below(WORDS.len() as u64) as usize];
+        let _ = write!(
+            doc,
+            "\n  {{\"id\": {i}, \"device\": \"{word}-{}\", \"timestamp\": {}, \
+             \"temperature\": {}.{}, \"online\": {}, \"error\": null, \
+             \"tags\": [\"{}\", \"{}\"], \"location\": {{\"lat\": {}.{:04}, \"lon\": -{}.{:04}}}}}",
+            rng.below(1000),
+            1_700_000_000 + rng.below(10_000_000),
+            rng.below(60),
+            rng.below(100),
+            rng.below(2) == 0,
+            WORDS[rng.below(WORDS.len() as u64) as usize],
+            WORDS[rng.below(WORDS.len() as u64) as usize],
+            rng.below(90),
+            rng.below(10_000),
+            rng.below(180),
+            rng.below(10_000),
+        );
+    }
+
+    doc.push_str("\n]");
+    doc
+}
+
+//a settings file, mostly keys and short strings
+fn config(sections: usize) -> String {
+    let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+    let mut doc = String::from("{");
+
+    for s in 0..sections {
+        if s > 0 {
+            doc.push(',');
+        }
+
+        let _ = write!(doc, "\n\t\"section_{s}\": {{");
+        for k in 0..12 {
+            if k > 0 {
+                doc.push(',');
+            }
+
+            let word = WORDS[rng.below(WORDS.len() as u64) as usize];
+            let _ = match rng.below(4) {
+                0 => write!(doc, "\n\t\t\"{word}_{k}\": \"/usr/share/{word}/{k}.conf\""),
+                1 => write!(doc, "\n\t\t\"{word}_{k}\": {}", rng.below(65536)),
+                2 => write!(doc, "\n\t\t\"{word}_{k}\": {}", rng.below(2) == 0),
+                _ => write!(doc, "\n\t\t\"{word}_{k}\": [\"{word}\", \"{word}\"]"),
+            };
+        }
+        doc.push_str("\n\t}");
+    }
+
+    doc.push_str("\n}");
+    doc
+}
+
+//a long run of numbers, as in a data series
+fn numbers(count: usize) -> String {
+    let mut rng = Rng(0xD1B5_4A32_D192_ED03);
+    let mut doc = String::from("[");
+
+    for i in 0..count {
+        if i > 0 {
+            doc.push(',');
+        }
+
+        let _ = match rng.below(3) {
+            0 => write!(doc, "{}", rng.next() as i64 >> 20),
+            1 => write!(doc, "{}.{}", rng.below(1000), rng.below(1_000_000)),
+            _ => write!(doc, "{}e-{}", rng.below(100), rng.below(20)),
+        };
+    }
+
+    doc.push(']');
+    doc
+}
+
+//strings that are mostly escapes and characters outside ascii
+fn escapes(count: usize) -> String {
+    let mut doc = String::from("[");
+
+    for i in 0..count {
+        if i > 0 {
+            doc.push(',');
+        }
+
+        let _ = write!(
+            doc,
+            "\"line {i}\\n\\ttab \\\"quoted\\\" \\\\ \\u00e9\\u4e2d \\/ done\", \"plain text {i} ünïcödé\""
+        );
+    }
+
+    doc.push(']');
+    doc
+}
+
+//log lines with long messages, where most of the input is inside strings
+fn logs(count: usize) -> String {
+    let mut rng = Rng(0x5851_F42D_4C95_7F2D);
+    let mut doc = String::from("[");
+
+    for i in 0..count {
+        if i > 0 {
+            doc.push(',');
+        }
+
+        let mut message = String::new();
+        for _ in 0..8 + rng.below(24) {
+            message.push_str(WORDS[rng.below(WORDS.len() as u64) as usize]);
+            message.push(' ');
+        }
+
+        let _ = write!(
+            doc,
+            "\n    {{\n        \"seq\": {i},\n        \"level\": \"info\",\n        \
+             \"message\": \"{message}\"\n    }}"
+        );
+    }
+
+    doc.push_str("\n]");
+    doc
+}
+
+//objects in arrays in objects, many small containers
+fn nested(width: usize, depth: usize) -> String {
+    fn level(doc: &mut String, width: usize, depth: usize) {
+        if depth == 0 {
+            doc.push_str("{\"leaf\": true, \"n\": 1}");
+            return;
+        }
+
+        doc.push_str("{\"children\": [");
+        for i in 0..width {
+            if i > 0 {
+                doc.push(',');
+            }
+            level(doc, width, depth - 1);
+        }
+        let _ = write!(doc, "], \"depth\": {depth}}}");
+    }
+
+    let mut doc = String::new();
+    level(&mut doc, width, depth);
+    doc
+}
+
+struct Measure {
+    mb_per_s: f64,
+    allocs: usize,
+}
+
+//reads every value and keeps none, what is left is the time spent scanning the input
+struct Discard;
+
+impl JsonVisitor for Discard {}
+
+fn measure<T, F: Fn(&str) -> Option<T>>(doc: &str, parse: F) -> Measure {
+    //once to warm up, which also counts the allocations of one document
+    let before = ALLOCS.load(Ordering::Relaxed);
+    let parsed = black_box(parse(black_box(doc)));
+    let allocs = ALLOCS.load(Ordering::Relaxed) - before;
+    assert!(parsed.is_some(), "the benchmark document does not parse");
+    drop(parsed);
+
+    let mut runs = 0u32;
+    let start = Instant::now();
+    while start.elapsed() < MIN_TIME {
+        black_box(parse(black_box(doc)));
+        runs += 1;
+    }
+
+    let secs = start.elapsed().as_secs_f64() / runs as f64;
+    Measure {
+        mb_per_s: doc.len() as f64 / secs / 1e6,
+        allocs,
+    }
+}
+
+fn main() {
+    let filter = std::env::args().skip(1).find(|arg| !arg.starts_with('-'));
+    let docs = [
+        ("telemetry", telemetry(5_000)),
+        ("config", config(400)),
+        ("numbers", numbers(100_000)),
+        ("escapes", escapes(10_000)),
+        ("logs", logs(10_000)),
+        ("nested", nested(4, 7)),
+    ];
+
+    println!(
+        "{:<10} {:>9} {:>12} {:>13} {:>12} {:>13} {:>14} {:>11}",
+        "document",
+        "size",
+        "legacy MB/s",
+        "current MB/s",
+        "events MB/s",
+        "legacy alloc",
+        "current alloc",
+        "skip alloc"
+    );
+
+    for (name, doc) in &docs {
+        if filter.as_ref().is_some_and(|f| !name.contains(f.as_str())) {
+            continue;
+        }
+
+        let legacy = measure(doc, legacy::parse);
+        let current = measure(doc, |doc| {
+            parser::parse_with(doc, ParseOptions::strict()).ok()
+        });
+        let events = measure(doc, |doc| {
+            parser::parse_events(doc, ParseOptions::strict(), &mut Discard).ok()
+        });
+        let skip = measure(doc, |doc| {
+            parser::PullParser::new(doc, ParseOptions::strict())
+                .skip_value()
+                .ok()
+        });
+
+        println!(
+            "{:<10} {:>8}K {:>12.1} {:>13.1} {:>12.1} {:>13} {:>14} {:>11}",
+            name,
+            doc.len() / 1024,
+            legacy.mb_per_s,
+            current.mb_per_s,
+            events.mb_per_s,
+            legacy.allocs,
+            current.allocs,
+            skip.allocs
+        );
+    }
+}
@@ -1,5 +1,8 @@
+fn main() {}
+
 #[cfg(test)]
 mod tests {
+    use fksjson::parser::Span;
     use fksjson::*;
 
     #[test]
@@ -7,7 +10,7 @@ mod tests {
         let (mut cxt, root_arr) = JsonContext::new(false);
         let (root, root_obj) = cxt.val_obj();
         cxt.array_push(root_arr, root);
-        
+
         cxt.set_val(root_obj, "asd".to_string(), cxt.val_int(-56));
         cxt.set_val(root_obj, "try".to_string(), cxt.val_int(560));
 
@@ -57,11 +60,1410 @@ mod tests {
     #[test]
     fn parsing() {
         let json = std::fs::read_to_string("test.json").unwrap();
-        let txt = parser::parse(&json)
-                 .unwrap().0.to_string(false);
-        
+        let txt = parser::parse(&json).unwrap().0.to_string(false);
+
         println!("\n\n{}\n\n", parser::parse(&txt).unwrap().0.to_string(true));
     }
-}
 
-fn main() {}
+    #[test]
+    fn strict_parsing() {
+        use parser::{ParseOptions, ParserError};
+
+        let strict = ParseOptions::strict();
+        let rejects = |json: &str| parser::parse_with(json, strict).err().unwrap();
+
+        assert!(parser::parse_with("{\"a\": [1, -2.5e3, \"\\u0041\"]}", strict).is_ok());
+        assert!(matches!(
+            rejects("[1] // hi"),
+            ParserError::CommentNotAllowed(Span {
+                line: 1,
+                pos: 5,
+                ..
+            })
+        ));
+        assert!(matches!(
+            rejects("[1, 2,]"),
+            ParserError::TrailingCommaNotAllowed(Span {
+                line: 1,
+                pos: 6,
+                ..
+            })
+        ));
+        assert!(matches!(
+            rejects("{\"a\": 1,}"),
+            ParserError::TrailingCommaNotAllowed(Span {
+                line: 1,
+                pos: 8,
+                ..
+            })
+        ));
+        assert!(matches!(
+            rejects("[+1]"),
+            ParserError::LeadingPlusNotAllowed(Span {
+                line: 1,
+                pos: 2,
+                ..
+            })
+        ));
+        assert!(matches!(
+            rejects("[0x1F]"),
+            ParserError::RadixNotAllowed(
+                'x',
+                Span {
+                    line: 1,
+                    pos: 3,
+                    ..
+                }
+            )
+        ));
+        assert!(matches!(
+            rejects("[017]"),
+            ParserError::RadixNotAllowed(
+                '1',
+                Span {
+                    line: 1,
+                    pos: 3,
+                    ..
+                }
+            )
+        ));
+        assert!(matches!(
+            rejects("[1.5f]"),
+            ParserError::FloatSuffixNotAllowed(
+                'f',
+                Span {
+                    line: 1,
+                    pos: 5,
+                    ..
+                }
+            )
+        ));
+        assert!(matches!(
+            rejects("[\"\\v\"]"),
+            ParserError::EscapeNotAllowed(
+                'v',
+                Span {
+                    line: 1,
+                    pos: 3,
+                    ..
+                }
+            )
+        ));
+        assert!(matches!(
+            rejects("[\"a\tb\"]"),
+            ParserError::ControlCharInString(
+                '\t',
+                Span {
+                    line: 1,
+                    pos: 4,
+                    ..
+                }
+            )
+        ));
+
+        let relaxed = ParseOptions::relaxed();
+        let (cxt, root) =
+            parser::parse_with("[0xFF, 0b11, 0o17, 017, +1, 1.5f, \"\\x41\",]", relaxed).unwrap();
+        assert_eq!(cxt.to_string(false), "[255,3,15,15,1,1.5,\"A\"]");
+        assert_eq!(cxt.get_int(cxt.array_at(root, 0)), 255);
+    }
+
+    #[test]
+    fn scalar_root() {
+        let (cxt, _) = parser::parse("  -12 ").unwrap();
+        assert_eq!(cxt.get_int(cxt.root()), -12);
+        assert_eq!(cxt.to_string(true), "-12");
+
+        let (cxt, _) = parser::parse("\"bus\\n\"").unwrap();
+        assert_eq!(cxt.get_string(cxt.root()), "bus\n");
+        assert_eq!(cxt.to_string(false), "\"bus\\n\"");
+
+        for (json, typ) in [
+            ("true", JsonType::Boolean),
+            ("null", JsonType::Null),
+            ("1.5", JsonType::Float),
+        ] {
+            let (cxt, _) = parser::parse(json).unwrap();
+            assert_eq!(cxt.root().get_type(), &typ);
+            assert_eq!(cxt.to_string(false), json);
+        }
+
+        assert!(matches!(
+            parser::parse("1 2"),
+            Err(parser::ParserError::UnexpectedToken(
+                _,
+                Span {
+                    line: 1,
+                    pos: 3,
+                    ..
+                }
+            ))
+        ));
+        assert!(matches!(
+            parser::parse(","),
+            Err(parser::ParserError::BadBeginning(
+                _,
+                Span {
+                    line: 1,
+                    pos: 1,
+                    ..
+                }
+            ))
+        ));
+
+        let mut cxt = JsonContext::new_empty();
+        let name = cxt.val_string("Felix".to_string());
+        cxt.set_root(name);
+        assert_eq!(cxt.to_string(false), "\"Felix\"");
+    }
+
+    //hands out the input a few bytes at a time
+    struct Trickle<'a>(&'a [u8]);
+
+    impl std::io::Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.0.len().min(buf.len()).min(3);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn reader_parsing() {
+        let json = std::fs::read_to_string("test.json").unwrap();
+        let (cxt, _) = parser::parse_reader(Trickle(json.as_bytes()), Default::default()).unwrap();
+        assert_eq!(
+            cxt.to_string(false),
+            parser::parse(&json).unwrap().0.to_string(false)
+        );
+
+        let (cxt, root) =
+            parser::parse_reader("[\"Grüße 😀\"]".as_bytes(), Default::default()).unwrap();
+        assert_eq!(cxt.get_string(cxt.array_at(root, 0)), "Grüße 😀");
+
+        let err = parser::parse_reader(Trickle(b"[\n \"a\xFFb\"]"), Default::default());
+        assert!(matches!(
+            err,
+            Err(parser::ParserError::InvalidUtf8(Span {
+                line: 2,
+                pos: 4,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn float_round_trip() {
+        let cases = [
+            ("0.1", 0.1f64),
+            ("0.30000000000000004", 0.1 + 0.2),
+            ("1e-10", 1e-10),
+            ("1.3445e245", 1.3445e245),
+            ("-0.000023", -0.000023),
+            (
+                "2.2250738585072011e-308",
+                f64::from_bits(0x000F_FFFF_FFFF_FFFF),
+            ),
+            ("2.2250738585072014e-308", f64::MIN_POSITIVE),
+            ("4.9406564584124654e-324", f64::from_bits(1)),
+            ("1.7976931348623157e308", f64::MAX),
+            ("1.2345678901234567", 1.2345678901234567),
+            ("9007199254740993.0", 9007199254740992.0),
+            ("1.0000000000000002", 1.0 + f64::EPSILON),
+            ("0.0000000000000000000000000000001e31", 1.0),
+            ("-0.0", -0.0),
+        ];
+
+        for (literal, expected) in cases {
+            let (cxt, root) = parser::parse(&format!("[{literal}]")).unwrap();
+            let parsed = cxt.get_float(cxt.array_at(root, 0));
+            assert_eq!(parsed.to_bits(), expected.to_bits(), "{literal}");
+
+            let (cxt, root) = parser::parse(&cxt.to_string(false)).unwrap();
+            let reparsed = cxt.get_float(cxt.array_at(root, 0));
+            assert_eq!(
+                reparsed.to_bits(),
+                expected.to_bits(),
+                "{literal} round trip"
+            );
+        }
+
+        assert_eq!(
+            parser::parse("[1.5e3, 1e400]").unwrap().0.to_string(false),
+            "[1500.0,null]"
+        );
+    }
+
+    #[test]
+    fn integer_limits() {
+        let (cxt, root) = parser::parse(
+            "[9223372036854775807, -9223372036854775808, 9223372036854775808, 18446744073709551615, 0xFFFFFFFFFFFFFFFF, -0x8000000000000000]",
+        )
+        .unwrap();
+        assert_eq!(cxt.get_int(cxt.array_at(root, 0)), i64::MAX);
+        assert_eq!(cxt.get_int(cxt.array_at(root, 1)), i64::MIN);
+        assert_eq!(cxt.array_at(root, 2).get_type(), &JsonType::UInt);
+        assert_eq!(cxt.get_uint(cxt.array_at(root, 2)), 1 << 63);
+        assert_eq!(cxt.get_uint(cxt.array_at(root, 3)), u64::MAX);
+        assert_eq!(cxt.get_uint(cxt.array_at(root, 4)), u64::MAX);
+        assert_eq!(cxt.get_int(cxt.array_at(root, 5)), i64::MIN);
+        assert_eq!(cxt.get_uint(cxt.array_at(root, 0)), i64::MAX as u64);
+        assert_eq!(
+            cxt.to_string(false),
+            "[9223372036854775807,-9223372036854775808,9223372036854775808,18446744073709551615,18446744073709551615,-9223372036854775808]"
+        );
+
+        for json in [
+            "[18446744073709551616]",
+            "[-9223372036854775809]",
+            "[ 0x10000000000000000]",
+            "[-0b11111111111111111111111111111111111111111111111111111111111111111]",
+        ] {
+            let err = parser::parse(json).err().unwrap();
+            assert!(
+                matches!(
+                    err,
+                    parser::ParserError::IntegerOverflow(Span {
+                        line: 1,
+                        pos: 2 | 3,
+                        ..
+                    })
+                ),
+                "{json}: {err:?}"
+            );
+        }
+
+        let mut cxt = JsonContext::new_empty();
+        cxt.set_root(cxt.val_uint(u64::MAX));
+        assert_eq!(cxt.to_string(false), "18446744073709551615");
+    }
+
+    #[test]
+    fn preserved_numbers() {
+        let opts = parser::ParseOptions {
+            preserve_numbers: true,
+            ..Default::default()
+        };
+
+        let json = "{\"amount\":123456789012345678901234567.890,\"id\":98765432109876543210,\"small\":-42,\"hex\":0x1F}";
+        let (cxt, root) = parser::parse_with(json, opts).unwrap();
+        assert_eq!(cxt.to_string(false), json.replace("0x1F", "31"));
+
+        //only what JSON has is written as it is, the rest keeps its value
+        let every = parser::ParseOptions {
+            preserve_numbers: true,
+            radix_integers: true,
+            float_suffixes: true,
+            ..parser::ParseOptions::json5()
+        };
+        let (odd, _) =
+            parser::parse_with("[0x1F, +1, Infinity, 1.5f, .5, 5., 007, 1e5]", every).unwrap();
+        assert_eq!(odd.to_string(false), "[31,1,null,1.5,0.5,5.0,7,1e5]");
+        let json5 = WriteOptions {
+            dialect: Dialect::Json5,
+            ..Default::default()
+        };
+        assert_eq!(
+            odd.to_string_with(&json5),
+            "[0x1F,+1,Infinity,1.5,.5,5.,7,1e5]"
+        );
+        let relaxed = WriteOptions {
+            dialect: Dialect::Relaxed,
+            ..Default::default()
+        };
+        assert_eq!(
+            odd.to_string_with(&relaxed),
+            "[0x1F,+1,null,1.5f,0.5,5.0,007,1e5]"
+        );
+
+        //too long for any integer type, every digit is still kept
+        let (huge, _) =
+            parser::parse_with("[0x1FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF, -0o777]", every).unwrap();
+        assert_eq!(
+            huge.to_string(false),
+            "[680564733841876926926749214863536422911,-511]"
+        );
+        assert_eq!(
+            huge.to_string_with(&relaxed),
+            "[0x1FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF,-0o777]"
+        );
+
+        let amount = cxt.get_val(root, &"amount".to_string());
+        assert_eq!(amount.get_type(), &JsonType::Number);
+        assert_eq!(cxt.get_number(amount), "123456789012345678901234567.890");
+        assert_eq!(cxt.get_number_i64(amount), None);
+        assert_eq!(cxt.get_number_f64(amount), Some(1.2345678901234568e26));
+
+        let id = cxt.get_val(root, &"id".to_string());
+        assert_eq!(cxt.get_number_u64(id), None);
+        assert_eq!(cxt.get_number_i128(id), Some(98765432109876543210));
+
+        let small = cxt.get_val(root, &"small".to_string());
+        assert_eq!(cxt.get_number_i64(small), Some(-42));
+        assert_eq!(cxt.get_number_u64(small), None);
+        assert_eq!(
+            cxt.get_number_i64(cxt.get_val(root, &"hex".to_string())),
+            Some(31)
+        );
+
+        assert!(matches!(
+            parser::parse_with("[1.2.3]", opts),
+            Err(parser::ParserError::UnexpectedChar(
+                '.',
+                Span {
+                    line: 1,
+                    pos: 5,
+                    ..
+                }
+            ))
+        ));
+
+        let mut cxt = JsonContext::new_empty();
+        let num = cxt.val_number("1.50".to_string());
+        cxt.set_root(num);
+        assert_eq!(cxt.to_string(false), "1.50");
+    }
+
+    #[test]
+    fn surrogate_pairs() {
+        let (cxt, root) = parser::parse(r#"["\uD83D\uDE00", "a\uD83D\uDE00b", "\u00E9"]"#).unwrap();
+        assert_eq!(cxt.get_string(cxt.array_at(root, 0)), "😀");
+        assert_eq!(cxt.get_string(cxt.array_at(root, 1)), "a😀b");
+        assert_eq!(cxt.get_string(cxt.array_at(root, 2)), "é");
+
+        let lone = [
+            (r#"["\uD83D"]"#, 0xD83D, 3),
+            (r#"["\uDE00"]"#, 0xDE00, 3),
+            (r#"["x\uD83Dy"]"#, 0xD83D, 4),
+            (r#"["\uD83D\n"]"#, 0xD83D, 3),
+            (r#"["\uD83D😀"]"#, 0xD83D, 3),
+        ];
+        for (json, code, pos) in lone {
+            let err = parser::parse(json).err().unwrap();
+            assert!(
+                matches!(err, parser::ParserError::LoneSurrogate(c, Span { line: 1, pos: p, .. }) if c == code && p == pos),
+                "{json}: {err:?}"
+            );
+        }
+
+        let opts = parser::ParseOptions {
+            replace_lone_surrogates: true,
+            ..Default::default()
+        };
+        let (cxt, root) =
+            parser::parse_with(r#"["\uD83D\n", "\uDE00x", "\uD83D😀"]"#, opts).unwrap();
+        assert_eq!(cxt.get_string(cxt.array_at(root, 0)), "\u{FFFD}\n");
+        assert_eq!(cxt.get_string(cxt.array_at(root, 1)), "\u{FFFD}x");
+        assert_eq!(cxt.get_string(cxt.array_at(root, 2)), "\u{FFFD}😀");
+    }
+
+    #[test]
+    fn unicode_output() {
+        let mut cxt = JsonContext::new_empty();
+        let text = cxt.val_string("Grüße 😀 𠀀".to_string());
+        cxt.set_root(text);
+
+        let escaped = cxt.to_string(false);
+        assert_eq!(escaped, r#""Gr\u00FC\u00DFe \uD83D\uDE00 \uD840\uDC00""#);
+        let (parsed, _) = parser::parse(&escaped).unwrap();
+        assert_eq!(parsed.get_string(parsed.root()), "Grüße 😀 𠀀");
+
+        let raw = cxt.to_string_with(&WriteOptions {
+            escape_unicode: false,
+            ..Default::default()
+        });
+        assert_eq!(raw, "\"Grüße 😀 𠀀\"");
+    }
+
+    #[test]
+    fn standard_escapes() {
+        let mut cxt = JsonContext::new_empty();
+        let text = cxt.val_string("'q\" \\ \n\t\r\x08\x0C \x0B\0\x01\x1F".to_string());
+        cxt.set_root(text);
+
+        let json = cxt.to_string(false);
+        assert_eq!(json, r#""'q\" \\ \n\t\r\b\f \u000B\u0000\u0001\u001F""#);
+        let (parsed, _) = parser::parse_with(&json, parser::ParseOptions::strict()).unwrap();
+        assert_eq!(parsed.get_string(parsed.root()), cxt.get_string(cxt.root()));
+
+        let relaxed = cxt.to_string_with(&WriteOptions {
+            dialect: Dialect::Relaxed,
+            ..Default::default()
+        });
+        assert_eq!(relaxed, r#""\'q\" \\ \n\t\r\b\f \v\0\x01\x1F""#);
+        let (parsed, _) = parser::parse(&relaxed).unwrap();
+        assert_eq!(parsed.get_string(parsed.root()), cxt.get_string(cxt.root()));
+
+        //`\0` before a digit would not read back
+        let mut cxt = JsonContext::new_empty();
+        let text = cxt.val_string("\x001\x00a".to_string());
+        cxt.set_root(text);
+        let relaxed = cxt.to_string_with(&WriteOptions {
+            dialect: Dialect::Relaxed,
+            ..Default::default()
+        });
+        assert_eq!(relaxed, r#""\x001\0a""#);
+        let (parsed, _) = parser::parse(&relaxed).unwrap();
+        assert_eq!(parsed.get_string(parsed.root()), "\x001\x00a");
+    }
+
+    #[test]
+    fn error_spans() {
+        let json = "{\n\t\"key\": tru,\n}";
+        let err = parser::parse(json).err().unwrap();
+        let span = err.span();
+        assert_eq!((span.start, span.end, span.line, span.pos), (10, 13, 2, 9));
+        assert_eq!(
+            err.render(json),
+            "error[E0007]: expected `true`\n --> 2:9\n  |\n2 | \t\"key\": tru,\n  | \t       ^^^"
+        );
+
+        let json = "[1, \"é\", 0x1G]";
+        let err = parser::parse(json).err().unwrap();
+        assert_eq!(&json[err.span().start..err.span().end], "G");
+        assert_eq!(err.span().pos, 13);
+        assert!(err
+            .render(json)
+            .ends_with("1 | [1, \"é\", 0x1G]\n  |             ^"));
+
+        let json = "[1,\n2";
+        let err = parser::parse(json).err().unwrap();
+        assert_eq!((err.span().start, err.span().line), (5, 2));
+        assert!(err.render(json).ends_with("2 | 2\n  |  ^"));
+    }
+
+    #[test]
+    fn error_display() -> Result<(), Box<dyn std::error::Error>> {
+        let (cxt, root) = parser::parse("[1]")?;
+        assert_eq!(cxt.get_int(cxt.array_at(root, 0)), 1);
+
+        let err = parser::parse("[1 2]").err().unwrap();
+        assert_eq!(err.code(), "E0006");
+        assert_eq!(err.to_string(), "E0006: expected `,` at line 1, position 4");
+
+        let boxed: Box<dyn std::error::Error> = Box::new(err);
+        assert!(boxed.source().is_none());
+
+        struct Broken;
+        impl std::io::Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("disk on fire"))
+            }
+        }
+
+        let err = parser::parse_reader(Broken, Default::default())
+            .err()
+            .unwrap();
+        assert_eq!(err.code(), "E0026");
+        assert!(std::error::Error::source(&err).is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn error_recovery() {
+        let json = r#"{
+            "name": "app",
+            "port": 80x,
+            "tags": ["a", tru, "b",],
+            "name": 2,
+            "nested": {"ok": true "bad": 1},
+            "last": [1, 2"#;
+        let (cxt, root, errors) = parser::parse_recovering(json, parser::ParseOptions::strict());
+        let found: Vec<_> = errors.iter().map(|e| (e.code(), e.span().line)).collect();
+        assert_eq!(
+            found,
+            [
+                ("E0012", 3),
+                ("E0007", 4),
+                ("E0018", 4),
+                ("E0002", 5),
+                ("E0006", 6),
+                ("E0005", 7)
+            ]
+        );
+        assert_eq!(
+            cxt.to_string(false),
+            r#"{"name":"app","tags":["a","b"],"nested":{"ok":true,"bad":1},"last":[1,2]}"#
+        );
+        assert_eq!(
+            cxt.get_string(cxt.get_val(root, &"name".to_string())),
+            "app"
+        );
+
+        let (cxt, _, errors) =
+            parser::parse_recovering("[1, }, 2, [3 4], \"\\q\"]", parser::ParseOptions::strict());
+        assert_eq!(cxt.to_string(false), r#"[1,2,[3,4],""]"#);
+        let codes: Vec<_> = errors.iter().map(|e| e.code()).collect();
+        assert_eq!(codes, ["E0009", "E0006", "E0011"]);
+
+        let (cxt, _, errors) = parser::parse_recovering("[1, 2]", parser::ParseOptions::strict());
+        assert!(errors.is_empty());
+        assert_eq!(cxt.to_string(false), "[1,2]");
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let depth = 200_000;
+        let json = "[".repeat(depth) + &"]".repeat(depth);
+
+        let err = parser::parse(&json).err().unwrap();
+        assert!(matches!(
+            err,
+            parser::ParserError::DepthLimitExceeded(128, Span { pos: 129, .. })
+        ));
+
+        let opts = parser::ParseOptions {
+            max_depth: usize::MAX,
+            ..Default::default()
+        };
+        let (cxt, _) = parser::parse_with(&json, opts).unwrap();
+        assert_eq!(cxt.to_string(false), json);
+
+        let mut cxt = JsonContext::new_empty();
+        let (root, mut obj_id) = cxt.val_obj();
+        cxt.set_root(root);
+        for _ in 0..depth {
+            let (obj, id) = cxt.val_obj();
+            cxt.set_val(obj_id, "a".to_string(), obj);
+            obj_id = id;
+        }
+        let json = cxt.to_string(false);
+        assert_eq!(json.len(), depth * 6 + 2);
+        assert!(json.starts_with(r#"{"a":{"a":{"#));
+        cxt.set_root(cxt.val_null());
+
+        let opts = parser::ParseOptions {
+            max_depth: 2,
+            ..Default::default()
+        };
+        let (cxt, _, errors) =
+            parser::parse_recovering(r#"[[1], [[2, [3]]], {"a": [4]}, 5]"#, opts);
+        assert_eq!(cxt.to_string(false), r#"[[1],[],{},5]"#);
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn resource_limits() {
+        use parser::ParserError;
+
+        let limited = parser::ParseOptions {
+            max_document_size: 64,
+            max_string_length: 8,
+            max_object_members: 2,
+            max_array_length: 3,
+            ..parser::ParseOptions::strict()
+        };
+        let rejects = |json: &str| parser::parse_with(json, limited).err().unwrap();
+
+        let (cxt, _) = parser::parse_with(r#"{"a": [1, 2, 3], "b": "12345678"}"#, limited).unwrap();
+        assert_eq!(cxt.to_string(false), r#"{"a":[1,2,3],"b":"12345678"}"#);
+
+        let err = rejects(&format!("[{}]", " ".repeat(70)));
+        assert!(matches!(
+            err,
+            ParserError::DocumentTooLarge(64, Span { start: 64, .. })
+        ));
+
+        let err = rejects(r#"["123456789"]"#);
+        assert!(matches!(
+            err,
+            ParserError::StringTooLong(
+                8,
+                Span {
+                    start: 1,
+                    end: 11,
+                    ..
+                }
+            )
+        ));
+        let err = rejects(r#"{"\u0041\u0041\u0041\u0041\u0041\u0041\u0041\u0041\u0041": 1}"#);
+        assert!(matches!(err, ParserError::StringTooLong(8, _)));
+
+        let err = rejects(r#"{"a": 1, "b": 2, "c": 3}"#);
+        assert!(matches!(
+            err,
+            ParserError::TooManyMembers(2, Span { pos: 18, .. })
+        ));
+
+        let err = rejects("[1, 2, 3, [4]]");
+        assert!(matches!(
+            err,
+            ParserError::ArrayTooLong(3, Span { pos: 11, .. })
+        ));
+
+        let (cxt, _, errors) = parser::parse_recovering("[[1, 2, 3, [4], 5], 6]", limited);
+        assert_eq!(cxt.to_string(false), "[[1,2,3],6]");
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let json = r#"{"a": 1, "b": {"x": 1}, "a": [2], "b": {"y": 2}, "a": 3}"#;
+        let with = |duplicate_keys| {
+            let opts = parser::ParseOptions {
+                duplicate_keys,
+                ..Default::default()
+            };
+            parser::parse_with(json, opts).map(|(cxt, _)| cxt.to_string(false))
+        };
+
+        assert!(matches!(
+            with(DuplicateKeys::Error),
+            Err(parser::ParserError::KeyExists(_, Span { pos: 25, .. }))
+        ));
+        assert_eq!(
+            with(DuplicateKeys::LastWins).unwrap(),
+            r#"{"a":3,"b":{"y":2}}"#
+        );
+        assert_eq!(
+            with(DuplicateKeys::FirstWins).unwrap(),
+            r#"{"a":1,"b":{"x":1}}"#
+        );
+        assert_eq!(
+            with(DuplicateKeys::CollectIntoArray).unwrap(),
+            r#"{"a":[1,[2],3],"b":[{"x":1},{"y":2}]}"#
+        );
+
+        let opts = parser::ParseOptions {
+            duplicate_keys: DuplicateKeys::FirstWins,
+            ..parser::ParseOptions::strict()
+        };
+        assert!(parser::parse_with(r#"{"a": 1, "a": [1,]}"#, opts).is_err());
+
+        let (mut cxt, root) = JsonContext::new(true);
+        let key = || "k".to_string();
+        let one = cxt.val_int(1);
+        assert_eq!(
+            cxt.set_val_with(root, key(), one, DuplicateKeys::Error)
+                .ok(),
+            Some(false)
+        );
+        let two = cxt.val_string("2".to_string());
+        let back = cxt
+            .set_val_with(root, key(), two, DuplicateKeys::Error)
+            .err()
+            .unwrap();
+        assert_eq!(back.get_type(), &JsonType::String);
+        assert_eq!(
+            cxt.set_val_with(root, key(), back, DuplicateKeys::LastWins)
+                .ok(),
+            Some(true)
+        );
+        assert_eq!(cxt.to_string(false), r#"{"k":"2"}"#);
+    }
+
+    #[test]
+    fn json5() {
+        let json = "{\n  // comment\n  unquoted: 'and you can quote me on that',\n  \
+                    singleQuotes: 'I can use \"double quotes\" here',\n  \
+                    lineBreaks: \"Look, Mom! \\\nNo \\\\n's!\",\n  hexadecimal: 0xdecaf,\n  \
+                    leadingDecimalPoint: .8675309, andTrailing: 8675309.,\n  \
+                    positiveSign: +1,\n  trailingComma: 'in objects', andIn: ['arrays',],\n  \
+                    \"backwardsCompatible\": \"with JSON\",\n  inf: -Infinity, nan: NaN,\u{A0}\n}\n";
+
+        let (cxt, _) = parser::parse_with(json, parser::ParseOptions::json5()).unwrap();
+        let opts = WriteOptions {
+            dialect: Dialect::Json5,
+            ..Default::default()
+        };
+        assert_eq!(
+            cxt.to_string_with(&opts),
+            "{unquoted:\"and you can quote me on that\",\
+             singleQuotes:\"I can use \\\"double quotes\\\" here\",\
+             lineBreaks:\"Look, Mom! No \\\\n's!\",hexadecimal:912559,\
+             leadingDecimalPoint:0.8675309,andTrailing:8675309.0,positiveSign:1,\
+             trailingComma:\"in objects\",andIn:[\"arrays\"],\
+             backwardsCompatible:\"with JSON\",inf:-Infinity,nan:NaN}"
+        );
+        assert!(cxt.to_string(false).ends_with("\"inf\":null,\"nan\":null}"));
+
+        let (cxt, _) = parser::parse_with(
+            r"{'a-b': 1, cd: 2, null: 'x\q\v'}",
+            parser::ParseOptions::json5(),
+        )
+        .unwrap();
+        assert_eq!(cxt.to_string_with(&opts), r#"{"a-b":1,cd:2,null:"xq\v"}"#);
+
+        //a raw tab is fine in a JSON5 string, a raw line break is not
+        assert!(parser::parse_with("['a\tb']", parser::ParseOptions::json5()).is_ok());
+        assert!(matches!(
+            parser::parse_with("['a\nb']", parser::ParseOptions::json5()),
+            Err(parser::ParserError::ControlCharInString('\n', _))
+        ));
+
+        use parser::ParserError;
+        let strict = |json: &str| parser::parse_with(json, parser::ParseOptions::strict()).err();
+        assert!(matches!(
+            strict("{a: 1}"),
+            Some(ParserError::IdentifierKeyNotAllowed(Span { pos: 2, .. }))
+        ));
+        assert!(matches!(
+            strict("'a'"),
+            Some(ParserError::SingleQuotesNotAllowed(_))
+        ));
+        assert!(matches!(
+            strict("[NaN]"),
+            Some(ParserError::NonFiniteNotAllowed(_))
+        ));
+        assert!(matches!(
+            strict("[.5]"),
+            Some(ParserError::BareDecimalPointNotAllowed(_))
+        ));
+        assert!(matches!(
+            strict("[5.]"),
+            Some(ParserError::BareDecimalPointNotAllowed(_))
+        ));
+        assert!(matches!(
+            strict("[0x1F]"),
+            Some(ParserError::RadixNotAllowed(..))
+        ));
+        assert!(matches!(
+            parser::parse_with("[0o17]", parser::ParseOptions::json5()),
+            Err(ParserError::RadixNotAllowed(..))
+        ));
+
+        //`\0` is fine, but not right before a digit
+        let (cxt, _) = parser::parse_with(r"['a\0b']", parser::ParseOptions::json5()).unwrap();
+        assert_eq!(cxt.to_string(false), "[\"a\\u0000b\"]");
+        for opts in [
+            parser::ParseOptions::json5(),
+            parser::ParseOptions::relaxed(),
+        ] {
+            assert!(matches!(
+                parser::parse_with(r#"["a\01"]"#, opts),
+                Err(ParserError::StringEscapeChar(
+                    '0',
+                    Span {
+                        start: 3,
+                        end: 5,
+                        ..
+                    }
+                ))
+            ));
+        }
+
+        //only the JSON5 dialect has these, the relaxed default does not
+        for json in [
+            "{a: 1}",
+            "['x']",
+            "[NaN]",
+            "[.5]",
+            "[\"a\\\nb\"]",
+            "[1,\u{A0}2]",
+        ] {
+            assert!(parser::parse(json).is_err(), "{json}");
+            assert!(
+                parser::parse_with(json, parser::ParseOptions::json5()).is_ok(),
+                "{json}"
+            );
+        }
+    }
+
+    #[test]
+    fn comments() {
+        let json = "// editor settings\n{\n  // spaces per tab\n  \"tabSize\": 4, // was 2\n  \
+                    \"plugins\": [ /* none yet */ ],\n  \"theme\": {\n    \"name\": \"dark\"\n    \
+                    // more later\n  },\n  \"files\": [\n    \"a.rs\", // main\n    \"b.rs\"\n  ]\n}\n";
+
+        let (mut cxt, root) = parser::parse(json).unwrap();
+        assert_eq!(
+            cxt.comments(root, "tabSize"),
+            [
+                Comment::new_line(" spaces per tab"),
+                Comment {
+                    trailing: true,
+                    ..Comment::new_line(" was 2")
+                }
+            ]
+        );
+
+        let eight = cxt.val_int(8);
+        cxt.set_val(root, "tabSize".to_string(), eight);
+        assert_eq!(
+            cxt.to_string(true),
+            "// editor settings\n{\n\t\"plugins\": [\n\t], /* none yet */\n\t\"theme\": {\n\t\t\
+             \"name\": \"dark\" // more later\n\t},\n\t\"files\": [\n\t\t\"a.rs\", // main\n\t\t\
+             \"b.rs\"\n\t],\n\t// spaces per tab\n\t\"tabSize\": 8 // was 2\n}"
+        );
+        assert!(!cxt.to_string(false).contains("//"));
+
+        let (reparsed, _) = parser::parse(&cxt.to_string(true)).unwrap();
+        assert_eq!(reparsed.to_string(true), cxt.to_string(true));
+
+        assert_eq!(cxt.remove_comments(root, "tabSize").len(), 2);
+        cxt.add_comment(root, "plugins", Comment::new_block(" off "));
+        assert!(cxt
+            .to_string(true)
+            .contains("\t/* off */\n\t\"plugins\": [\n\t], /* none yet */\n"));
+
+        cxt.erase(root, "plugins".to_string());
+        let empty = cxt.val_null();
+        cxt.set_val(root, "plugins".to_string(), empty);
+        assert!(cxt.comments(root, "plugins").is_empty());
+
+        //text that would end the comment early is refused
+        assert!(std::panic::catch_unwind(|| Comment::new_block(" a */ {")).is_err());
+        assert!(std::panic::catch_unwind(|| Comment::new_line(" a\n{")).is_err());
+        let sneaky = Comment {
+            text: "x */ {".to_string(),
+            ..Comment::new_block("")
+        };
+        let mut cxt = std::panic::AssertUnwindSafe(cxt);
+        assert!(std::panic::catch_unwind(move || cxt.add_comment(root, "theme", sneaky)).is_err());
+    }
+
+    #[test]
+    fn cst_edits() {
+        use cst::{CstDocument, PathItem::*};
+
+        let text = "{\n    \"name\" : 'tool', // keep me\n    \"version\": \"1.2.3\",\n    \
+                    \"flags\": 0x1F,\n    \"deps\": [1, 2.50f],\n    /* the end */\n    \
+                    \"last\": true\n}\n";
+        let opts = parser::ParseOptions {
+            single_quotes: true,
+            ..parser::ParseOptions::relaxed()
+        };
+        let mut doc = CstDocument::parse_with(text, opts).unwrap();
+        assert_eq!(doc.get(&[Key("flags")]), Some("0x1F"));
+        assert_eq!(doc.get(&[Key("deps"), Index(1)]), Some("2.50f"));
+        assert_eq!(doc.get(&[Key("name")]), Some("'tool'"));
+
+        doc.set(&[Key("version")], "\"1.2.4\"").unwrap();
+        assert_eq!(doc.text(), text.replace("1.2.3", "1.2.4"));
+
+        doc.insert(&[Key("deps"), Index(0)], "0").unwrap();
+        doc.insert(&[Key("deps"), Index(3)], "3").unwrap();
+        assert_eq!(doc.get(&[Key("deps")]), Some("[0, 1, 2.50f, 3]"));
+
+        doc.insert(&[Key("extra")], "null").unwrap();
+        assert!(doc
+            .text()
+            .ends_with("    \"last\": true,\n    \"extra\": null\n}\n"));
+
+        doc.remove(&[Key("extra")]).unwrap();
+        doc.remove(&[Key("deps"), Index(0)]).unwrap();
+        doc.remove(&[Key("deps"), Index(2)]).unwrap();
+        assert_eq!(doc.text(), text.replace("1.2.3", "1.2.4"));
+
+        doc.remove(&[Key("version")]).unwrap();
+        assert_eq!(
+            doc.text(),
+            text.replace("    \"version\": \"1.2.3\",\n", "")
+        );
+
+        assert!(doc.set(&[Key("flags")], "[").is_err());
+        assert_eq!(doc.get(&[Key("flags")]), Some("0x1F"));
+
+        //one value only, nothing may spill into the text around it
+        let before = doc.text().to_string();
+        assert!(doc.set(&[Key("flags")], "1, \"evil\": 2").is_err());
+        assert!(doc.set(&[Key("flags")], "1 // eats the comma").is_err());
+        assert!(doc
+            .insert(&[Key("deps"), Index(0)], "0], \"x\": [")
+            .is_err());
+        assert_eq!(doc.text(), before);
+        doc.set(&[Key("flags")], " /* mask */ 2 ").unwrap();
+        assert_eq!(doc.get(&[Key("flags")]), Some("2"));
+        assert!(doc.node(&[Key("missing")]).is_none());
+
+        //the comment on the last member stays on its line
+        let mut doc = CstDocument::parse("{\n  \"a\": 1,\n  \"b\": 2 // about b\n}").unwrap();
+        doc.insert(&[Key("c")], "3").unwrap();
+        assert_eq!(
+            doc.text(),
+            "{\n  \"a\": 1,\n  \"b\": 2, // about b\n  \"c\": 3\n}"
+        );
+
+        let mut doc = CstDocument::parse("[\n  1, // one\n]").unwrap();
+        doc.insert(&[Index(1)], "2").unwrap();
+        assert_eq!(doc.text(), "[\n  1, // one\n  2,\n]");
+
+        let mut doc = CstDocument::parse("{\"a\": 1}").unwrap();
+        doc.insert(&[Key("b")], "2").unwrap();
+        assert_eq!(doc.text(), "{\"a\": 1, \"b\": 2}");
+        let mut doc = CstDocument::parse("[1]").unwrap();
+        doc.insert(&[Index(0)], "0").unwrap();
+        assert_eq!(doc.text(), "[0, 1]");
+    }
+
+    #[test]
+    fn ndjson_records() {
+        use ndjson::{parse_ndjson, read_ndjson, NdjsonWriter};
+
+        let text = "{\"id\": 1}\n\n[2, 3]\r\n{\"id\": }\n\"four\"\n";
+        let records: Vec<_> = parse_ndjson(text, parser::ParseOptions::strict()).collect();
+        assert_eq!(records.len(), 4);
+
+        let err = records[2].as_ref().err().unwrap();
+        assert_eq!((err.record, err.line), (2, 4));
+        assert!(matches!(
+            err.error,
+            parser::ParserError::UnexpectedToken(_, Span { pos: 8, .. })
+        ));
+        assert_eq!(
+            err.to_string(),
+            "record 2 (line 4): E0009: unexpected `}` at line 1, position 8"
+        );
+
+        let docs: Vec<JsonContext> = records
+            .into_iter()
+            .filter_map(|r| r.ok().map(|(cxt, _)| cxt))
+            .collect();
+
+        let mut writer = NdjsonWriter::new(Vec::new());
+        writer.write_all(&docs).unwrap();
+        let out = String::from_utf8(writer.into_inner()).unwrap();
+        assert_eq!(out, "{\"id\":1}\n[2,3]\n\"four\"\n");
+
+        let bytes = b"1\n\xFF\n2\n";
+        let read: Vec<_> = read_ndjson(&bytes[..], Default::default()).collect();
+        assert!(matches!(
+            read[1],
+            Err(ndjson::RecordError {
+                record: 1,
+                line: 2,
+                error: parser::ParserError::InvalidUtf8(_)
+            })
+        ));
+        assert!(read[2].is_ok());
+    }
+
+    #[test]
+    fn push_parsing() {
+        use parser::{ParseOptions, PushParser};
+
+        let json = "// chunks\n{\"name\": \"Grüße 😀\", \"n\": [1.5e3, -42, 0x1F, true, null],\n \
+                    \"deep\": {\"a\": {}}, /* end */ \"s\": \"\\u00e9\\n\"}\n";
+        let whole = parser::parse(json).unwrap().0.to_string(true);
+
+        let bytes = json.as_bytes();
+        for size in [1, 2, 3, 7, 64] {
+            let mut push = PushParser::new(ParseOptions::relaxed());
+            for chunk in bytes.chunks(size) {
+                push.feed(chunk).unwrap();
+            }
+            let (cxt, _) = push.finish().unwrap();
+            assert_eq!(cxt.to_string(true), whole);
+        }
+
+        //errors point at the same place as they do for the whole text
+        let bad = "{\n  \"a\": [1, 2,, 3]\n}";
+        let mut push = PushParser::new(ParseOptions::strict());
+        let err = bad
+            .as_bytes()
+            .chunks(2)
+            .map(|chunk| push.feed(chunk))
+            .find_map(Result::err)
+            .unwrap();
+        let expected = parser::parse_with(bad, ParseOptions::strict())
+            .err()
+            .unwrap();
+        assert_eq!(err.span(), expected.span());
+        assert_eq!(err.span().line, 2);
+        //the parser keeps giving the error
+        assert_eq!(push.feed(b"]").unwrap_err().span(), expected.span());
+        assert_eq!(push.finish().unwrap_err().span(), expected.span());
+
+        //a long string is not read again from its start for every chunk
+        let long = format!("[\"{}\", 12345678, true]", "x".repeat(2 << 20));
+        let mut push = PushParser::new(ParseOptions::strict());
+        for chunk in long.as_bytes().chunks(4096) {
+            push.feed(chunk).unwrap();
+        }
+        assert!(push.rescanned() < 2 * 4096, "{}", push.rescanned());
+        let (cxt, _) = push.finish().unwrap();
+        assert_eq!(cxt.to_string(false).len(), long.len() - 2);
+
+        let mut push = PushParser::new(ParseOptions::strict());
+        push.feed(b"12").unwrap();
+        push.feed(b"34 ").unwrap();
+        assert_eq!(push.finish().unwrap().0.to_string(false), "1234");
+
+        let mut push = PushParser::new(ParseOptions::strict());
+        push.feed(b"[\"\xC3").unwrap();
+        assert!(matches!(
+            push.finish(),
+            Err(parser::ParserError::InvalidUtf8(_))
+        ));
+
+        let push = PushParser::new(ParseOptions::strict());
+        assert!(matches!(
+            push.finish(),
+            Err(parser::ParserError::EmptyString(_))
+        ));
+    }
+
+    #[test]
+    fn event_visitor() {
+        use parser::{parse_events, JsonVisitor, ParseOptions, Visit};
+
+        #[derive(Default)]
+        struct Counter {
+            depth: usize,
+            records: usize,
+            ids: Vec<i64>,
+            in_id: bool,
+            stop_at: Option<usize>,
+        }
+
+        impl JsonVisitor for Counter {
+            fn start_object(&mut self) -> Visit {
+                self.depth += 1;
+                if self.depth == 1 {
+                    self.records += 1;
+                }
+                match self.stop_at {
+                    Some(n) if self.records > n => Visit::Stop,
+                    _ => Visit::Continue,
+                }
+            }
+
+            fn end_object(&mut self) -> Visit {
+                self.depth -= 1;
+                Visit::Continue
+            }
+
+            fn key(&mut self, key: &str) -> Visit {
+                self.in_id = key == "id";
+                Visit::Continue
+            }
+
+            fn int(&mut self, val: i64) -> Visit {
+                if self.in_id {
+                    self.ids.push(val);
+                }
+                Visit::Continue
+            }
+        }
+
+        let json = r#"[{"id": 1, "tags": ["a"]}, {"id": 2, "x": {"id": 9}}, {"id": 3}]"#;
+        let mut counter = Counter::default();
+        assert!(parse_events(json, ParseOptions::strict(), &mut counter).unwrap());
+        assert_eq!((counter.records, counter.ids), (3, vec![1, 2, 9, 3]));
+
+        let mut counter = Counter {
+            stop_at: Some(1),
+            ..Default::default()
+        };
+        //the rest is never read, mistakes in it go unnoticed
+        let broken = r#"[{"id": 1}, {"id": 2}, {"id": "#;
+        assert!(!parse_events(broken, ParseOptions::strict(), &mut counter).unwrap());
+        assert_eq!(counter.ids, vec![1]);
+
+        for bad in [
+            "",
+            "]",
+            "[1 2]",
+            "{\"a\": 1, \"a\": 2}",
+            "[1,]",
+            "{\"a\": [1}",
+            "[tru]",
+            "[[[[1]]]]",
+            "{a: 1}",
+            "[1] 2",
+        ] {
+            let opts = ParseOptions {
+                max_depth: 3,
+                ..ParseOptions::strict()
+            };
+            let expected = parser::parse_with(bad, opts).err().unwrap();
+            let err = parse_events(bad, opts, &mut Counter::default()).unwrap_err();
+            assert_eq!(err.code(), expected.code(), "{bad}");
+            assert_eq!(err.span(), expected.span(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn pull_parsing() {
+        use parser::{Event, ParseOptions, PullParser};
+
+        let json = r#"{"skip": {"a": [1, {"b": [2]}], "c": "}"}, "keep": [true, 1.5], "n": null}"#;
+        let mut pull = PullParser::new(json, ParseOptions::strict());
+
+        assert_eq!(pull.next_event().unwrap().unwrap().0, Event::StartObject);
+        let (key, span) = pull.peek().unwrap().unwrap();
+        assert_eq!(
+            (key, span.start, span.end),
+            (&Event::Key("skip".into()), 1, 7)
+        );
+
+        //the key and the whole object after it
+        let skipped = pull.skip_value().unwrap().unwrap();
+        assert_eq!(
+            &json[skipped.start..skipped.end],
+            r#"{"a": [1, {"b": [2]}], "c": "}"}"#
+        );
+
+        let events: Vec<Event> = std::iter::from_fn(|| pull.next_event().unwrap())
+            .map(|(event, _)| event)
+            .collect();
+        assert_eq!(
+            events,
+            [
+                Event::Key("keep".into()),
+                Event::StartArray,
+                Event::Bool(true),
+                Event::Float(1.5),
+                Event::EndArray,
+                Event::Key("n".into()),
+                Event::Null,
+                Event::EndObject,
+            ]
+        );
+        assert!(pull.next_event().unwrap().is_none());
+
+        //what is skipped is checked just like parse_with checks it
+        for bad in [
+            "[1, [2, 3}]",
+            r#"[{"a" 1 2}, 5]"#,
+            "[[1 2], 3]",
+            "[[1,, 2]]",
+        ] {
+            let mut pull = PullParser::new(bad, ParseOptions::strict());
+            pull.next_event().unwrap();
+            if bad.starts_with("[1") {
+                assert_eq!(pull.skip_value().unwrap().map(|s| s.end), Some(2));
+            }
+            let expected = parser::parse_with(bad, ParseOptions::strict()).err();
+            assert_eq!(
+                pull.skip_value().err().map(|e| e.to_string()),
+                expected.map(|e| e.to_string())
+            );
+        }
+
+        //strings are not kept while skipping, but their errors and limits are the same
+        let opts = ParseOptions {
+            duplicate_keys: DuplicateKeys::Error,
+            max_string_length: 8,
+            ..ParseOptions::strict()
+        };
+        for bad in [
+            r#"[["a\q"], 1]"#,
+            r#"[{"a": 1, "a": 2}]"#,
+            r#"[{"b": "123456789"}]"#,
+            r#"[{"\u00e9\u00e9\u00e9\u00e9\u00e9": 1}]"#,
+        ] {
+            let mut pull = PullParser::new(bad, opts);
+            pull.next_event().unwrap();
+            let expected = parser::parse_with(bad, opts).err();
+            assert!(expected.is_some());
+            assert_eq!(
+                pull.skip_value().err().map(|e| e.to_string()),
+                expected.map(|e| e.to_string())
+            );
+        }
+
+        let json5 = ParseOptions {
+            duplicate_keys: DuplicateKeys::Error,
+            ..ParseOptions::json5()
+        };
+        for bad in [
+            "[{a: 1, \\u0061: 2}]",
+            "[[tru], 1]",
+            "[{'a': 1, \"\\x61\": 2}]",
+        ] {
+            let mut pull = PullParser::new(bad, json5);
+            pull.next_event().unwrap();
+            let expected = parser::parse_with(bad, json5).err();
+            assert!(expected.is_some());
+            assert_eq!(
+                pull.skip_value().err().map(|e| e.to_string()),
+                expected.map(|e| e.to_string())
+            );
+        }
+
+        //and the events after it have theirs again
+        let mut pull = PullParser::new(r#"[{"a": "b"}, "c"]"#, opts);
+        pull.next_event().unwrap();
+        pull.skip_value().unwrap();
+        assert_eq!(
+            pull.next_event().unwrap().unwrap().0,
+            Event::String("c".into())
+        );
+
+        let mut pull = PullParser::new("[]", ParseOptions::strict());
+        pull.next_event().unwrap();
+        assert!(pull.skip_value().unwrap().is_none());
+        assert_eq!(pull.next_event().unwrap().unwrap().0, Event::EndArray);
+    }
+
+    #[test]
+    fn byte_input() {
+        use parser::{detect_encoding, parse_bytes, Encoding, ParserError};
+
+        let json = "{\"name\": \"Grüße 😀\"}";
+        let expected = parser::parse(json).unwrap().0.to_string(false);
+
+        let utf16le: Vec<u8> = json.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let utf16be: Vec<u8> = json.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        let utf32le: Vec<u8> = json
+            .chars()
+            .flat_map(|c| (c as u32).to_le_bytes())
+            .collect();
+        let utf32be: Vec<u8> = json
+            .chars()
+            .flat_map(|c| (c as u32).to_be_bytes())
+            .collect();
+        let with_bom = |bom: &[u8], body: &[u8]| [bom, body].concat();
+
+        for (bytes, enc) in [
+            (json.as_bytes().to_vec(), Encoding::Utf8),
+            (
+                with_bom(&[0xEF, 0xBB, 0xBF], json.as_bytes()),
+                Encoding::Utf8,
+            ),
+            (utf16le.clone(), Encoding::Utf16Le),
+            (with_bom(&[0xFF, 0xFE], &utf16le), Encoding::Utf16Le),
+            (utf16be.clone(), Encoding::Utf16Be),
+            (with_bom(&[0xFE, 0xFF], &utf16be), Encoding::Utf16Be),
+            (utf32le.clone(), Encoding::Utf32Le),
+            (with_bom(&[0xFF, 0xFE, 0, 0], &utf32le), Encoding::Utf32Le),
+            (utf32be.clone(), Encoding::Utf32Be),
+            (with_bom(&[0, 0, 0xFE, 0xFF], &utf32be), Encoding::Utf32Be),
+        ] {
+            assert_eq!(detect_encoding(&bytes).0, enc);
+            let (cxt, _) =
+                parser::parse_bytes_with(&bytes, parser::ParseOptions::strict()).unwrap();
+            assert_eq!(cxt.to_string(false), expected);
+        }
+
+        //offsets count the bom
+        assert!(matches!(
+            parse_bytes(b"\xEF\xBB\xBF[1,\n \"a\xFFb\"]"),
+            Err(ParserError::InvalidUtf8(Span {
+                start: 10,
+                end: 11,
+                line: 2,
+                pos: 4
+            }))
+        ));
+        assert!(matches!(
+            parse_bytes(b"\xEF\xBB\xBF[1 2]"),
+            Err(ParserError::ExpectedChar(',', Span { start: 6, .. }))
+        ));
+
+        //a high surrogate without its low half
+        let mut lone = with_bom(
+            &[0xFF, 0xFE],
+            &"[\""
+                .encode_utf16()
+                .flat_map(u16::to_le_bytes)
+                .collect::<Vec<u8>>(),
+        );
+        lone.extend_from_slice(&[0x3D, 0xD8, 0x22, 0x00, 0x5D, 0x00]);
+        let err = parse_bytes(&lone).unwrap_err();
+        assert!(matches!(
+            err,
+            ParserError::InvalidEncoding(
+                Encoding::Utf16Le,
+                Span {
+                    start: 6,
+                    end: 8,
+                    ..
+                }
+            )
+        ));
+        assert_eq!(
+            err.to_string(),
+            "E0036: the input is not valid utf-16le at line 1, position 3"
+        );
+    }
+
+    #[test]
+    fn single_pass() {
+        use parser::*;
+
+        //plain runs are copied whole, escapes and limits still go character by character
+        let text = "{\"plain ünï\": \"a long run \\\"then\\\" é\\u00e9 end\", null: [true, \"x\"]}";
+        let (cxt, root) = parse_with(text, ParseOptions::json5()).unwrap();
+        let val = cxt.get_val(root, &"plain ünï".to_string());
+        assert_eq!(cxt.get_string(val), "a long run \"then\" éé end");
+        assert!(cxt.contains_str(root, "null"));
+
+        let opts = ParseOptions {
+            max_string_length: 8,
+            ..ParseOptions::strict()
+        };
+        assert!(matches!(
+            parse_with("[\"0123456789\"]", opts),
+            Err(ParserError::StringTooLong(8, _))
+        ));
+
+        //punctuation has no text of its own, errors still spell it out
+        let err = parse_with("[1, }", ParseOptions::strict()).unwrap_err();
+        assert!(err.to_string().contains("unexpected `}`"), "{err}");
+        let err = parse_with("{\"a\": 1} true", ParseOptions::strict()).unwrap_err();
+        assert!(err.to_string().contains("`true`"), "{err}");
+
+        //numbers are read where the lexer left them, errors and keys still have their text
+        let err = parse_with("[1.5] -2e3", ParseOptions::strict()).unwrap_err();
+        assert!(err.to_string().contains("`-2e3`"), "{err}");
+        let err = parse_with("{\"a\" 42}", ParseOptions::strict()).unwrap_err();
+        assert!(err.to_string().contains("`42`"), "{err}");
+        let (cxt, root) =
+            parse_with("{Infinity: NaN, n: [0x10, 7]}", ParseOptions::json5()).unwrap();
+        assert!(cxt
+            .get_float(cxt.get_val(root, &"Infinity".to_string()))
+            .is_nan());
+        assert_eq!(cxt.to_string(false), "{\"Infinity\":null,\"n\":[16,7]}");
+
+        //the token after a run is where it should be
+        let err = parse_with("[\"ab€c\" x]", ParseOptions::strict()).unwrap_err();
+        assert_eq!(err.span().start, 10);
+        assert_eq!(err.span().pos, 9);
+    }
+
+    #[test]
+    fn run_scanning() {
+        use parser::*;
+
+        //plain runs of every length and offset, and the same from a reader
+        let long = "0123456789abcdé".repeat(9);
+        for pad in 0..70 {
+            let text = format!(
+                "{}[\"{long}\\n{long}\",{}\r\n\t1, '{long}']",
+                " ".repeat(pad),
+                " ".repeat(pad)
+            );
+            for (cxt, root) in [
+                parse_with(&text, ParseOptions::json5()).unwrap(),
+                parse_reader(text.as_bytes(), ParseOptions::json5()).unwrap(),
+            ] {
+                let val = cxt.array_at(root, 0);
+                assert_eq!(cxt.get_string(val), &format!("{long}\n{long}"));
+                assert_eq!(cxt.get_string(cxt.array_at(root, 2)), &long);
+            }
+
+            //the error is where the character by character scan puts it
+            let text = format!("[\"{long}\",\n{}\"a\tb\"]", " ".repeat(pad));
+            let err = parse_with(&text, ParseOptions::strict()).unwrap_err();
+            assert!(matches!(err, ParserError::ControlCharInString('\t', _)));
+            assert_eq!(err.span().line, 2);
+            assert_eq!(err.span().pos, pad + 3);
+            assert_eq!(err.span().start, long.len() + pad + 7);
+
+            //numbers end at the same byte whichever block they start in
+            let text = format!("{}[123456789.25e-3,-0x1F,7éx]", " ".repeat(pad));
+            let err = parse_with(&text, ParseOptions::relaxed()).unwrap_err();
+            assert!(matches!(err, ParserError::IntegerInvalidDecimal('é', _)));
+            let text = text.replace("7éx", "7");
+            let (cxt, _) = parse_with(&text, ParseOptions::relaxed()).unwrap();
+            assert_eq!(cxt.to_string(false), "[123456.78925,-31,7]");
+        }
+
+        //a reader's buffer is refilled under the runs
+        let text = format!(
+            "[{}]",
+            (0..3000)
+                .map(|i| format!("{i}.5e1, \"s{i}\""))
+                .collect::<Vec<_>>()
+                .join(",    ")
+        );
+        let whole = parse_with(&text, ParseOptions::strict()).unwrap().0;
+        let read = parse_reader(text.as_bytes(), ParseOptions::strict())
+            .unwrap()
+            .0;
+        assert_eq!(read.to_string(false), whole.to_string(false));
+    }
+}
@@ -0,0 +1,450 @@
+use crate::parser::{self, ParseOptions, ParserError, Span, Token, TokenType};
+use crate::{JsonObject, WriteOptions};
+
+/**
+* a document that keeps every byte of its text: whitespace, comments, the spelling of numbers
+* and the quotes of strings. the tree only points into the text, the trivia is whatever lies
+* between the tokens. edits splice the text and parse it again, everything they do not touch
+* stays exactly as it was.
+*/
+#[derive(Debug)]
+pub struct CstDocument {
+    text: String,
+    opts: ParseOptions,
+    root: CstNode,
+}
+
+#[derive(Debug)]
+pub struct CstNode {
+    pub span: Span, //the value alone, from its first to its last character
+    pub kind: CstKind,
+}
+
+#[derive(Debug)]
+pub enum CstKind {
+    Object(Vec<CstMember>),
+    Array(Vec<CstElement>),
+    Scalar,
+}
+
+#[derive(Debug)]
+pub struct CstMember {
+    pub key: String, //decoded, key_span has it as written
+    pub key_span: Span,
+    pub value: CstNode,
+    pub comma: Option<Span>,
+}
+
+#[derive(Debug)]
+pub struct CstElement {
+    pub value: CstNode,
+    pub comma: Option<Span>,
+}
+
+//one step into a document
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathItem<'a> {
+    Key(&'a str),
+    Index(usize),
+}
+
+//an object or array whose closer has not been read yet
+enum Open {
+    Object(Span, Vec<CstMember>, Option<(String, Span)>),
+    Array(Span, Vec<CstElement>),
+}
+
+impl CstNode {
+    //the span of the first character of a member or element, its key for members
+    fn item_starts(&self) -> Vec<usize> {
+        match &self.kind {
+            CstKind::Object(members) => members.iter().map(|m| m.key_span.start).collect(),
+            CstKind::Array(elements) => elements.iter().map(|e| e.value.span.start).collect(),
+            CstKind::Scalar => Vec::new(),
+        }
+    }
+
+    fn item_ends(&self) -> Vec<(usize, Option<Span>)> {
+        match &self.kind {
+            CstKind::Object(members) => members
+                .iter()
+                .map(|m| (m.value.span.end, m.comma))
+                .collect(),
+            CstKind::Array(elements) => elements
+                .iter()
+                .map(|e| (e.value.span.end, e.comma))
+                .collect(),
+            CstKind::Scalar => Vec::new(),
+        }
+    }
+
+    fn child(&self, item: PathItem) -> Option<&CstNode> {
+        match (&self.kind, item) {
+            (CstKind::Object(members), PathItem::Key(key)) => members
+                .iter()
+                .rev()
+                .find(|m| m.key == key)
+                .map(|m| &m.value),
+            (CstKind::Array(elements), PathItem::Index(idx)) => elements.get(idx).map(|e| &e.value),
+            _ => None,
+        }
+    }
+}
+
+impl CstDocument {
+    pub fn parse(text: &str) -> Result<Self, ParserError> {
+        Self::parse_with(text, ParseOptions::relaxed())
+    }
+
+    pub fn parse_with(text: &str, opts: ParseOptions) -> Result<Self, ParserError> {
+        let tokens = parser::tokenize(text, opts)?;
+        Ok(Self {
+            text: text.to_string(),
+            opts,
+            root: build(tokens),
+        })
+    }
+
+    pub fn root(&self) -> &CstNode {
+        &self.root
+    }
+
+    //the whole text, byte for byte
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn node(&self, path: &[PathItem]) -> Option<&CstNode> {
+        let mut node = &self.root;
+        for item in path {
+            node = node.child(*item)?;
+        }
+
+        Some(node)
+    }
+
+    //the value at path as it is written, `0x123` stays `0x123`
+    pub fn get(&self, path: &[PathItem]) -> Option<&str> {
+        let node = self.node(path)?;
+        Some(&self.text[node.span.start..node.span.end])
+    }
+
+    fn obtain_node(&self, path: &[PathItem], op: &str) -> &CstNode {
+        match self.node(path) {
+            Some(node) => node,
+            None => panic!("fksjson: path {path:?} does not exist [{op}]."),
+        }
+    }
+
+    //replaces the value at path with value, which is written as it is given
+    pub fn set(&mut self, path: &[PathItem], value: &str) -> Result<(), ParserError> {
+        let span = self.obtain_node(path, "set").span;
+        self.check_value(value)?;
+        self.splice(vec![(span.start, span.end, value.to_string())])
+    }
+
+    /**
+     * adds value at path. the last item is the new key, which goes after the other members,
+     * or the index the new element gets. separators and indentation are copied from the items
+     * that are already there.
+     */
+    pub fn insert(&mut self, path: &[PathItem], value: &str) -> Result<(), ParserError> {
+        let (last, parent_path) = match path.split_last() {
+            Some(split) => split,
+            None => panic!("fksjson: inserting at the root."),
+        };
+
+        let parent = self.obtain_node(parent_path, "insert");
+        self.check_value(value)?;
+        let (item, idx) = match (&parent.kind, *last) {
+            (CstKind::Object(members), PathItem::Key(key)) => {
+                if members.iter().any(|m| m.key == key) {
+                    panic!("fksjson: inserting key '{key}' that already exist.");
+                }
+
+                let colon = match members.last() {
+                    Some(m) => &self.text[m.key_span.end..m.value.span.start],
+                    None => ": ",
+                };
+                let colon = if colon.trim() == ":" { colon } else { ": " };
+
+                //bare keys stay bare if the others are
+                let bare = members
+                    .last()
+                    .is_some_and(|m| !self.text[m.key_span.start..].starts_with(['"', '\'']));
+
+                let mut item = String::new();
+                if bare && parser::is_identifier(key) {
+                    item.push_str(key);
+                } else {
+                    JsonObject::push_string(&mut item, key, &WriteOptions::default());
+                }
+                item.push_str(colon);
+                item.push_str(value);
+                (item, members.len())
+            }
+            (CstKind::Array(elements), PathItem::Index(idx)) => {
+                if idx > elements.len() {
+                    panic!(
+                        "fksjson: array index out of bounds of length {} [insert]. (idx={idx})",
+                        elements.len()
+                    );
+                }
+
+                (value.to_string(), idx)
+            }
+            _ => panic!("fksjson: path {path:?} does not fit the value [insert]."),
+        };
+
+        let starts = parent.item_starts();
+        let ends = parent.item_ends();
+        let edits = if starts.is_empty() {
+            //right after the `{` or `[`
+            vec![(parent.span.start + 1, parent.span.start + 1, item)]
+        } else if idx < starts.len() {
+            let sep = self.separator(parent, idx.max(1).min(starts.len() - 1));
+            vec![(starts[idx], starts[idx], format!("{item},{sep}"))]
+        } else {
+            //on a line of its own it goes after the last item's comment, which stays with it
+            let sep = self.separator(parent, starts.len() - 1);
+            let after = |pos| {
+                if sep.contains('\n') {
+                    self.line_rest(pos)
+                } else {
+                    pos
+                }
+            };
+            match ends[idx - 1] {
+                (_, Some(comma)) => {
+                    let at = after(comma.end);
+                    vec![(at, at, format!("{sep}{item},"))]
+                }
+                (end, None) => {
+                    let at = after(end);
+                    vec![
+                        (end, end, ",".to_string()),
+                        (at, at, format!("{sep}{item}")),
+                    ]
+                }
+            }
+        };
+
+        self.splice(edits)
+    }
+
+    //takes the member or element at path out, with its comma and the rest of its line
+    pub fn remove(&mut self, path: &[PathItem]) -> Result<(), ParserError> {
+        let (last, parent_path) = match path.split_last() {
+            Some(split) => split,
+            None => panic!("fksjson: removing the root."),
+        };
+
+        let parent = self.obtain_node(parent_path, "remove");
+        let idx = match (&parent.kind, *last) {
+            (CstKind::Object(members), PathItem::Key(key)) => {
+                members.iter().rposition(|m| m.key == key)
+            }
+            (CstKind::Array(elements), PathItem::Index(idx)) => {
+                (idx < elements.len()).then_some(idx)
+            }
+            _ => None,
+        };
+        let idx = match idx {
+            Some(idx) => idx,
+            None => panic!("fksjson: path {path:?} does not exist [remove]."),
+        };
+
+        let starts = parent.item_starts();
+        let ends = parent.item_ends();
+        let before = match idx {
+            0 => parent.span.start + 1,
+            _ => ends[idx - 1].1.map_or(ends[idx - 1].0, |comma| comma.end),
+        };
+        let mut end = ends[idx].1.map_or(ends[idx].0, |comma| comma.end);
+
+        let mut edits = Vec::new();
+        let start = match self.text[before..starts[idx]].rfind('\n') {
+            //on a line of its own, the line goes with it
+            Some(nl) => {
+                end = self.line_rest(end);
+                before + nl
+            }
+            None if idx == 0 => {
+                end += self.text[end..].len() - self.text[end..].trim_start().len();
+                starts[idx]
+            }
+            None => before,
+        };
+
+        //the last item loses its comma, the one before it gives its own up
+        if idx > 0 && idx + 1 == starts.len() && ends[idx].1.is_none() {
+            if let Some(comma) = ends[idx - 1].1 {
+                edits.push((comma.start, comma.end, String::new()));
+            }
+        }
+        edits.push((start, end, String::new()));
+
+        self.splice(edits)
+    }
+
+    //value must be one value and nothing after it: `1, "b": 2` would add a member and a line
+    //comment would swallow the text that follows
+    fn check_value(&self, value: &str) -> Result<(), ParserError> {
+        let tokens = parser::tokenize(value, self.opts)?;
+        let end = tokens.last().map_or(0, |tkn| tkn.span.end);
+        let rest = &value[end..];
+        if rest.trim().is_empty() {
+            return Ok(());
+        }
+
+        let before = &value[..end];
+        let line_start = before.rfind('\n').map_or(0, |nl| nl + 1);
+        let span = Span {
+            start: end,
+            end: value.len(),
+            line: before.matches('\n').count() + 1,
+            pos: before[line_start..].chars().count() + 1,
+        };
+        Err(ParserError::UnexpectedToken(rest.trim().to_string(), span))
+    }
+
+    //the whitespace in front of item idx that is not on a comment's line. the first item only
+    //has the opener before it, a space stands in unless it is on a line of its own
+    fn separator(&self, parent: &CstNode, idx: usize) -> String {
+        let starts = parent.item_starts();
+        let ends = parent.item_ends();
+        let before = match idx {
+            0 => parent.span.start + 1,
+            _ => ends[idx - 1].1.map_or(ends[idx - 1].0, |comma| comma.end),
+        };
+
+        let gap = &self.text[before..starts[idx]];
+        let sep = match gap.rfind('\n') {
+            Some(nl) => &gap[nl..],
+            None => gap,
+        };
+
+        if sep.trim().is_empty() && (idx > 0 || sep.contains('\n')) {
+            sep.to_string()
+        } else {
+            " ".to_string()
+        }
+    }
+
+    //skips the spaces and comments after pos up to the end of its line
+    fn line_rest(&self, pos: usize) -> usize {
+        let rest = &self.text[pos..];
+        let mut off = rest.len() - rest.trim_start_matches([' ', '\t']).len();
+
+        loop {
+            let tail = &rest[off..];
+            if tail.starts_with("//") {
+                return pos + off + tail.find('\n').unwrap_or(tail.len());
+            }
+
+            match tail.strip_prefix("/*").and_then(|t| t.find("*/")) {
+                Some(close) if !tail[..close + 4].contains('\n') => {
+                    off += close + 4;
+                    off += rest[off..].len() - rest[off..].trim_start_matches([' ', '\t']).len();
+                }
+                _ => break,
+            }
+        }
+
+        if rest[off..].starts_with(['\n', '\r']) || off == rest.len() {
+            pos + off
+        } else {
+            pos
+        }
+    }
+
+    //applies edits (start, end, replacement), which must not overlap. the document is only
+    //changed if the new text parses
+    fn splice(&mut self, mut edits: Vec<(usize, usize, String)>) -> Result<(), ParserError> {
+        edits.sort_by_key(|edit| edit.0);
+
+        let mut text = self.text.clone();
+        for (start, end, with) in edits.into_iter().rev() {
+            text.replace_range(start..end, &with);
+        }
+
+        *self = Self::parse_with(&text, self.opts)?;
+        Ok(())
+    }
+}
+
+//puts the tree together from the tokens of a document that is known to parse
+fn build(tokens: Vec<Token>) -> CstNode {
+    let mut stack: Vec<Open> = Vec::new();
+    let mut root = None;
+
+    for tkn in tokens {
+        let node = match tkn.typ {
+            TokenType::OBrk => {
+                stack.push(Open::Object(tkn.span, Vec::new(), None));
+                continue;
+            }
+            TokenType::OSBrk => {
+                stack.push(Open::Array(tkn.span, Vec::new()));
+                continue;
+            }
+            TokenType::Colon => continue,
+            TokenType::Comma => {
+                match stack.last_mut() {
+                    Some(Open::Object(_, members, _)) => {
+                        members.last_mut().unwrap().comma = Some(tkn.span);
+                    }
+                    Some(Open::Array(_, elements)) => {
+                        elements.last_mut().unwrap().comma = Some(tkn.span);
+                    }
+                    None => {}
+                }
+                continue;
+            }
+            TokenType::CBrk | TokenType::CSBrk => {
+                let (start, kind) = match stack.pop().unwrap() {
+                    Open::Object(start, members, _) => (start, CstKind::Object(members)),
+                    Open::Array(start, elements) => (start, CstKind::Array(elements)),
+                };
+
+                let span = Span {
+                    end: tkn.span.end,
+                    ..start
+                };
+                CstNode { span, kind }
+            }
+            _ => {
+                //a key when the object is waiting for one
+                if let Some(Open::Object(_, _, key @ None)) = stack.last_mut() {
+                    let span = tkn.span;
+                    *key = Some((tkn.into_text(), span));
+                    continue;
+                }
+
+                CstNode {
+                    span: tkn.span,
+                    kind: CstKind::Scalar,
+                }
+            }
+        };
+
+        match stack.last_mut() {
+            Some(Open::Object(_, members, key)) => {
+                let (key, key_span) = key.take().unwrap();
+                members.push(CstMember {
+                    key,
+                    key_span,
+                    value: node,
+                    comma: None,
+                });
+            }
+            Some(Open::Array(_, elements)) => elements.push(CstElement {
+                value: node,
+                comma: None,
+            }),
+            None => root = Some(node),
+        }
+    }
+
+    root.unwrap()
+}
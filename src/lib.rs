@@ -1,11 +1,17 @@
+use indexmap::map::Entry;
 use indexmap::IndexMap;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::mem;
 
+pub mod cst;
+pub mod ndjson;
 pub mod parser;
 
 #[derive(Debug, PartialEq)]
 pub enum JsonType {
     Int,
+    UInt,   //integers above i64::MAX
+    Number, //the original text of a number, see ParseOptions::preserve_numbers
     Float,
     Boolean,
     String,
@@ -14,6 +20,95 @@ pub enum JsonType {
     Null,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    Json,    //RFC 8259
+    Relaxed, //also the \' \v \0 and \x escapes that parser::ParseOptions::relaxed reads
+    Json5,   //unquoted keys, \v and \x escapes, Infinity and NaN
+}
+
+//what JsonContext::set_val_with and parser::ParseOptions::duplicate_keys do with a key that
+//is already in the object
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateKeys {
+    Error,            //leave the object as it is
+    LastWins,         //replace the value, the key keeps its place
+    FirstWins,        //keep the old value, drop the new one
+    CollectIntoArray, //an array of every value, in the order they were set
+}
+
+//a comment kept from the document. text is everything between the `//` and the end of its
+//line, or between the `/*` and the `*/`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    pub text: String,
+    pub block: bool,    //a `/* */` comment, otherwise a `//` one
+    pub trailing: bool, //after the value on the same line, otherwise on the lines before it
+}
+
+impl Comment {
+    pub fn new_line(text: &str) -> Self {
+        let comment = Self {
+            text: text.to_string(),
+            block: false,
+            trailing: false,
+        };
+        comment.check();
+        comment
+    }
+
+    pub fn new_block(text: &str) -> Self {
+        let comment = Self {
+            text: text.to_string(),
+            block: true,
+            trailing: false,
+        };
+        comment.check();
+        comment
+    }
+
+    //text that would end the comment early cannot be written back
+    fn check(&self) {
+        if self.block && self.text.contains("*/") {
+            panic!("fksjson: block comment text '{}' contains `*/`.", self.text);
+        }
+
+        if !self.block && self.text.contains('\n') {
+            panic!(
+                "fksjson: line comment text {:?} contains a line break.",
+                self.text
+            );
+        }
+    }
+}
+
+//where the parser attaches a comment
+pub(crate) enum CommentSlot {
+    Root,
+    Member(u64, String),
+    Element(u64, usize),
+}
+
+//how JsonContext::to_string_with writes a document
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteOptions {
+    pub beautify: bool,
+    pub escape_unicode: bool, //\u escapes for everything above ascii, off writes raw utf-8
+    pub dialect: Dialect,
+    pub comments: bool, //the kept comments, only written when beautifying
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        Self {
+            beautify: false,
+            escape_unicode: true,
+            dialect: Dialect::Json,
+            comments: true,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct JsonValue {
     val: u64,
@@ -23,6 +118,7 @@ pub struct JsonValue {
 #[derive(Debug)]
 pub struct JsonObject {
     values: IndexMap<String, JsonValue>,
+    comments: HashMap<String, Vec<Comment>>,
 }
 
 #[derive(Debug)]
@@ -33,63 +129,70 @@ pub struct JsonContext {
     object_id: u64,
     arrays: HashMap<u64, Vec<JsonValue>>,
     array_id: u64,
-    initial_obj: bool,
+    collected: HashSet<u64>, //the arrays made by DuplicateKeys::CollectIntoArray
+    element_comments: HashMap<u64, Vec<Vec<Comment>>>, //by array, then by index
+    root_comments: Vec<Comment>,
+    root: JsonValue,
 }
 
 impl JsonValue {
     pub fn get_type(&self) -> &JsonType {
-        return &self.typ;
+        &self.typ
     }
 }
 
 impl JsonContext {
     pub fn new(initial_obj: bool) -> (Self, u64) {
-        let mut objs = HashMap::<u64, JsonObject>::new();
-        let mut arrs = HashMap::<u64, Vec<JsonValue>>::new();
-
-        let mut obj_id = 0;
-        let mut arr_id = 0;
-
-        if initial_obj {
-            objs.insert(
-                0,
-                JsonObject {
-                    values: IndexMap::new(),
-                },
-            );
-            obj_id += 1;
+        let mut cxt = Self::new_empty();
+        let (root, root_id) = if initial_obj {
+            cxt.val_obj()
         } else {
-            arrs.insert(0, Vec::new());
-            arr_id += 1;
-        }
-
-        (
-            Self {
-                strings: HashMap::new(),
-                string_id: 0,
-                objects: objs,
-                object_id: obj_id,
-                arrays: arrs,
-                array_id: arr_id,
-                initial_obj,
+            cxt.val_array()
+        };
+
+        cxt.root = root;
+        (cxt, root_id)
+    }
+
+    //a context whose root is null, replace it with set_root
+    pub fn new_empty() -> Self {
+        Self {
+            strings: HashMap::new(),
+            string_id: 0,
+            objects: HashMap::new(),
+            object_id: 0,
+            arrays: HashMap::new(),
+            array_id: 0,
+            collected: HashSet::new(),
+            element_comments: HashMap::new(),
+            root_comments: Vec::new(),
+            root: JsonValue {
+                val: 0,
+                typ: JsonType::Null,
             },
-            0,
-        )
+        }
+    }
+
+    pub fn root(&self) -> &JsonValue {
+        &self.root
+    }
+
+    pub fn set_root(&mut self, val: JsonValue) {
+        let old = std::mem::replace(&mut self.root, val);
+        self.release_value(old);
     }
 
     pub fn to_string(&self, beautify: bool) -> String {
+        self.to_string_with(&WriteOptions {
+            beautify,
+            ..Default::default()
+        })
+    }
+
+    pub fn to_string_with(&self, opts: &WriteOptions) -> String {
         let mut str = String::new();
-        if self.initial_obj {
-            let root = self.objects.get(&0u64).unwrap();
-            str.push_str(root.to_string(&self, 0, beautify).as_str());
-        } else {
-            let arr_val = JsonValue {
-                val: 0u64,
-                typ: JsonType::Array,
-            };
-            JsonObject::str_push_value(&self, &mut str, &arr_val, 0, beautify);
-        }
-        return str;
+        JsonObject::str_push_value(self, &mut str, &self.root, 0, opts);
+        str
     }
 
     fn obtain_object(&self, obj_id: u64) -> &JsonObject {
@@ -101,29 +204,11 @@ impl JsonContext {
     }
 
     fn validate_contains(&self, obj_id: u64, key: &String) {
-        if !self.contains(obj_id, &key) {
+        if !self.contains(obj_id, key) {
             panic!("fksjson: erasing key '{key}' that does not exist.");
         }
     }
 
-    fn clear_obj_vals(&mut self, values: &mut IndexMap<String, JsonValue>) {
-        for (key, _) in values.iter() {
-            let val = values.get(key).unwrap();
-            match val.typ {
-                JsonType::String => {
-                    self.strings.remove(&val.val);
-                }
-                JsonType::Object => {
-                    let mut obj_rem = self.objects.remove(&val.val).unwrap();
-                    self.clear_obj_vals(&mut obj_rem.values);
-                }
-                _ => {}
-            }
-        }
-
-        values.clear();
-    }
-
     fn erase_no_panic(&mut self, obj_id: u64, key: &String) {
         if !self.objects.contains_key(&obj_id) || !self.contains(obj_id, key) {
             return;
@@ -143,19 +228,24 @@ impl JsonContext {
             }
         }
 
-        let value = value_op.unwrap();
+        self.release_value(value_op.unwrap());
+    }
 
-        match value.typ {
-            JsonType::String => {
-                self.strings.remove(&value.val);
-            }
-            JsonType::Object => {
-                let obj_rem_id = value.val;
-                let mut obj_rem = self.objects.remove(&obj_rem_id).unwrap();
+    fn release_value(&mut self, value: JsonValue) {
+        //a list of the values left to free instead of recursion, objects nest to any depth
+        let mut pending = vec![value];
 
-                self.clear_obj_vals(&mut obj_rem.values);
+        while let Some(value) = pending.pop() {
+            match value.typ {
+                JsonType::String | JsonType::Number => {
+                    self.strings.remove(&value.val);
+                }
+                JsonType::Object => {
+                    let obj_rem = self.objects.remove(&value.val).unwrap();
+                    pending.extend(obj_rem.values.into_values());
+                }
+                _ => {}
             }
-            _ => {}
         }
     }
 
@@ -182,6 +272,13 @@ impl JsonContext {
         }
     }
 
+    pub fn val_uint(&self, val: u64) -> JsonValue {
+        JsonValue {
+            val,
+            typ: JsonType::UInt,
+        }
+    }
+
     pub fn val_float(&self, val: f64) -> JsonValue {
         JsonValue {
             val: f64::to_bits(val),
@@ -216,6 +313,19 @@ impl JsonContext {
         value
     }
 
+    //keeps the exact text of a number, written back out unchanged when the dialect has that form
+    pub fn val_number(&mut self, text: String) -> JsonValue {
+        if !parser::is_number(&text) {
+            panic!("fksjson: '{text}' is not a number.");
+        }
+
+        let value = self.val_string(text);
+        JsonValue {
+            val: value.val,
+            typ: JsonType::Number,
+        }
+    }
+
     pub fn val_obj(&mut self) -> (JsonValue, u64) {
         let object_id = self.object_id;
         let value = JsonValue {
@@ -228,6 +338,7 @@ impl JsonContext {
             self.object_id,
             JsonObject {
                 values: IndexMap::new(),
+                comments: HashMap::new(),
             },
         );
         self.object_id += 1;
@@ -255,6 +366,54 @@ impl JsonContext {
         self.obtain_object_mut(obj_id).values.insert(key, val);
     }
 
+    /**
+     * set_val that tells whether the key was already in the object, and then follows policy
+     * instead of always replacing the value. Ok(true) if the key existed.
+     * with DuplicateKeys::Error the object is left as it is and val is given back.
+     */
+    pub fn set_val_with(
+        &mut self,
+        obj_id: u64,
+        key: String,
+        val: JsonValue,
+        policy: DuplicateKeys,
+    ) -> Result<bool, JsonValue> {
+        //the key is looked up once, a duplicate is then found by its index
+        let idx = match self.obtain_object_mut(obj_id).values.entry(key) {
+            Entry::Vacant(entry) => {
+                entry.insert(val);
+                return Ok(false);
+            }
+            Entry::Occupied(entry) => entry.index(),
+        };
+
+        match policy {
+            DuplicateKeys::Error => return Err(val),
+            DuplicateKeys::LastWins => {
+                let old = mem::replace(&mut self.obtain_object_mut(obj_id).values[idx], val);
+                self.release_value(old);
+            }
+            DuplicateKeys::FirstWins => self.release_value(val),
+            DuplicateKeys::CollectIntoArray => {
+                let old = &self.obtain_object(obj_id).values[idx];
+                if old.typ == JsonType::Array && self.collected.contains(&old.val) {
+                    let arr_id = old.val;
+                    self.array_push(arr_id, val);
+                } else {
+                    //an array that was a value of its own is collected like any other value
+                    let (arr, arr_id) = self.val_array();
+                    self.collected.insert(arr_id);
+
+                    let old = mem::replace(&mut self.obtain_object_mut(obj_id).values[idx], arr);
+                    self.array_push(arr_id, old);
+                    self.array_push(arr_id, val);
+                }
+            }
+        }
+
+        Ok(true)
+    }
+
     pub fn get_int(&self, val: &JsonValue) -> i64 {
         if val.typ != JsonType::Int {
             panic!("fksjson: expected integer value.");
@@ -263,6 +422,15 @@ impl JsonContext {
         val.val as i64
     }
 
+    //non-negative Int values are accepted too, the parser only makes UInt above i64::MAX
+    pub fn get_uint(&self, val: &JsonValue) -> u64 {
+        match val.typ {
+            JsonType::UInt => val.val,
+            JsonType::Int if val.val as i64 >= 0 => val.val,
+            _ => panic!("fksjson: expected unsigned integer value."),
+        }
+    }
+
     pub fn get_float(&self, val: &JsonValue) -> f64 {
         if val.typ != JsonType::Float {
             panic!("fksjson: expected floating point value.");
@@ -287,6 +455,31 @@ impl JsonContext {
         self.strings.get(&val.val).unwrap()
     }
 
+    pub fn get_number(&self, val: &JsonValue) -> &String {
+        if val.typ != JsonType::Number {
+            panic!("fksjson: expected number value.");
+        }
+
+        self.strings.get(&val.val).unwrap()
+    }
+
+    //the number getters give None when the value does not fit the type
+    pub fn get_number_i64(&self, val: &JsonValue) -> Option<i64> {
+        parser::parse_i128(self.get_number(val)).and_then(|n| i64::try_from(n).ok())
+    }
+
+    pub fn get_number_u64(&self, val: &JsonValue) -> Option<u64> {
+        parser::parse_i128(self.get_number(val)).and_then(|n| u64::try_from(n).ok())
+    }
+
+    pub fn get_number_i128(&self, val: &JsonValue) -> Option<i128> {
+        parser::parse_i128(self.get_number(val))
+    }
+
+    pub fn get_number_f64(&self, val: &JsonValue) -> Option<f64> {
+        parser::parse_f64(self.get_number(val))
+    }
+
     pub fn get_obj(&self, val: &JsonValue) -> &JsonObject {
         if val.typ != JsonType::Object {
             panic!("fksjson: expected object value.");
@@ -297,10 +490,10 @@ impl JsonContext {
 
     pub fn get_val(&self, obj_id: u64, key: &String) -> &JsonValue {
         let val = self.objects.get(&obj_id).unwrap().values.get(key);
-        return match val {
+        match val {
             Some(v) => v,
             None => panic!("fksjson: key '{key}' does not exist [get]."),
-        };
+        }
     }
 
     fn array_vec_get(&self, arr_id: u64) -> &Vec<JsonValue> {
@@ -325,15 +518,28 @@ impl JsonContext {
 
     pub fn array_insert(&mut self, arr_id: u64, idx: usize, val: JsonValue) {
         self.array_vec_get_mut(arr_id).insert(idx, val);
+
+        //the comments stay with the elements they belong to
+        if let Some(comments) = self.element_comments.get_mut(&arr_id) {
+            if idx < comments.len() {
+                comments.insert(idx, Vec::new());
+            }
+        }
     }
 
     pub fn array_pop(&mut self, arr_id: u64) -> JsonValue {
         let arr = self.array_vec_get_mut(arr_id);
-        if arr.len() == 0 {
+        if arr.is_empty() {
             panic!("fksjson: array is empty [pop]. (id={arr_id})");
         }
 
-        arr.pop().unwrap()
+        let val = arr.pop().unwrap();
+        let len = arr.len();
+        if let Some(comments) = self.element_comments.get_mut(&arr_id) {
+            comments.truncate(len);
+        }
+
+        val
     }
 
     pub fn array_remove(&mut self, arr_id: u64, idx: usize) -> JsonValue {
@@ -345,12 +551,19 @@ impl JsonContext {
             );
         }
 
-        arr.remove(idx)
+        let val = arr.remove(idx);
+        if let Some(comments) = self.element_comments.get_mut(&arr_id) {
+            if idx < comments.len() {
+                comments.remove(idx);
+            }
+        }
+
+        val
     }
 
     pub fn array_top(&self, arr_id: u64) -> &JsonValue {
         let arr = self.array_vec_get(arr_id);
-        if arr.len() == 0 {
+        if arr.is_empty() {
             panic!("fksjson: array is empty [top]. (id={arr_id})");
         }
 
@@ -379,139 +592,374 @@ impl JsonContext {
     pub fn erase(&mut self, obj_id: u64, key: String) {
         self.validate_contains(obj_id, &key);
         self.erase_no_panic(obj_id, &key);
+        self.obtain_object_mut(obj_id).comments.remove(&key);
+    }
+
+    //the comments attached to a key, set_val keeps them and erase removes them
+    pub fn comments(&self, obj_id: u64, key: &str) -> &[Comment] {
+        match self.obtain_object(obj_id).comments.get(key) {
+            Some(comments) => comments,
+            None => &[],
+        }
+    }
+
+    pub fn add_comment(&mut self, obj_id: u64, key: &str, comment: Comment) {
+        if !self.contains_str(obj_id, key) {
+            panic!("fksjson: commenting key '{key}' that does not exist.");
+        }
+        comment.check();
+
+        let obj = self.obtain_object_mut(obj_id);
+        obj.comments
+            .entry(key.to_string())
+            .or_default()
+            .push(comment);
+    }
+
+    pub fn remove_comments(&mut self, obj_id: u64, key: &str) -> Vec<Comment> {
+        let obj = self.obtain_object_mut(obj_id);
+        obj.comments.remove(key).unwrap_or_default()
+    }
+
+    pub(crate) fn push_comment(&mut self, slot: &CommentSlot, comment: Comment) {
+        match slot {
+            CommentSlot::Root => self.root_comments.push(comment),
+            CommentSlot::Member(obj_id, key) => {
+                let obj = self.obtain_object_mut(*obj_id);
+                obj.comments.entry(key.clone()).or_default().push(comment);
+            }
+            CommentSlot::Element(arr_id, idx) => {
+                let comments = self.element_comments.entry(*arr_id).or_default();
+                if comments.len() <= *idx {
+                    comments.resize(*idx + 1, Vec::new());
+                }
+                comments[*idx].push(comment);
+            }
+        }
+    }
+
+    pub(crate) fn last_key(&self, obj_id: u64) -> Option<&String> {
+        self.obtain_object(obj_id).values.last().map(|(key, _)| key)
     }
 }
 
-impl JsonObject {    
-    fn push_string(str: &mut String, val: &String) {
+impl JsonObject {
+    fn push_hex(str: &mut String, val: u32, digits: u32) {
+        for i in (0..digits).rev() {
+            let shift = i * 4;
+            let digit = ((val >> shift) & 0xF) as u8;
+            if digit < 10 {
+                str.push((digit + b'0') as char);
+            } else {
+                str.push((digit + b'A' - 10u8) as char);
+            }
+        }
+    }
+
+    fn push_string(str: &mut String, val: &str, opts: &WriteOptions) {
+        let relaxed = opts.dialect == Dialect::Relaxed;
+        let extended = relaxed || opts.dialect == Dialect::Json5;
         str.push('\"');
 
-        for c in val.chars() {
+        let mut chars = val.chars().peekable();
+        while let Some(c) = chars.next() {
             match c {
                 '\"' => str.push_str("\\\""),
-                '\'' => str.push_str("\\\'"),
                 '\\' => str.push_str("\\\\"),
                 '\n' => str.push_str("\\n"),
                 '\t' => str.push_str("\\t"),
-                '\x0B' => str.push_str("\\v"),
                 '\r' => str.push_str("\\r"),
-                '\0' => str.push_str("\\0"),
                 '\x08' => str.push_str("\\b"),
                 '\x0C' => str.push_str("\\f"),
-                _ => {
-                    let c_num = c as u32;
-                    if c_num >= 128 {
-                        str.push_str("\\u");
-
-                        for i in (0..4).rev() {
-                            let shift = i * 4;
-                            let digit = ((c_num >> shift) & 0xF) as u8;
-                            if digit < 10 {
-                                str.push((digit + b'0') as char);
-                            } else {
-                                str.push((digit + b'A' - 10u8) as char);
-                            }
-                        }
+                '\'' if relaxed => str.push_str("\\\'"),
+                '\x0B' if extended => str.push_str("\\v"),
+                '\0' if relaxed && !chars.peek().is_some_and(char::is_ascii_digit) => {
+                    str.push_str("\\0")
+                }
+                '\0'..='\x1F' => {
+                    //json5 has \0 too, but not when a digit follows, \x00 is always safe
+                    if extended {
+                        str.push_str("\\x");
+                        Self::push_hex(str, c as u32, 2);
                     } else {
+                        str.push_str("\\u");
+                        Self::push_hex(str, c as u32, 4);
+                    }
+                }
+                _ => {
+                    if c.is_ascii() || !opts.escape_unicode {
                         str.push(c);
+                        continue;
+                    }
+
+                    //characters above U+FFFF are written as a utf-16 surrogate pair
+                    let mut units = [0u16; 2];
+                    for unit in c.encode_utf16(&mut units) {
+                        str.push_str("\\u");
+                        Self::push_hex(str, *unit as u32, 4);
                     }
                 }
             }
         }
-        
+
         str.push('\"');
     }
-    
+
+    //Debug gives the shortest digits that read back to the same bits, always with a '.' or
+    //an exponent so it stays a float. json has no infinity or NaN, they become null.
+    fn push_float(str: &mut String, val: f64, opts: &WriteOptions) {
+        if val.is_finite() {
+            str.push_str(&format!("{val:?}"));
+        } else if opts.dialect != Dialect::Json5 {
+            str.push_str("null");
+        } else if val.is_nan() {
+            str.push_str("NaN");
+        } else if val > 0.0 {
+            str.push_str("Infinity");
+        } else {
+            str.push_str("-Infinity");
+        }
+    }
+
+    //a preserved number as it was written if the dialect has it, otherwise the same value in
+    //a form it does have: `+1` is `1`, `.5` is `0.5`, `0x1F` is `31` however long it is.
+    //only Infinity and NaN are left to push_float
+    fn push_number(str: &mut String, text: &str, opts: &WriteOptions) {
+        let dialect = match opts.dialect {
+            Dialect::Json => parser::ParseOptions::strict(),
+            Dialect::Relaxed => parser::ParseOptions::relaxed(),
+            Dialect::Json5 => parser::ParseOptions::json5(),
+        };
+        if parser::is_number_with(text, &dialect) {
+            str.push_str(text);
+            return;
+        }
+
+        //decimals only need their spelling fixed, which keeps every digit
+        let (sign, body) = match text.as_bytes().first() {
+            Some(b'-') => ("-", &text[1..]),
+            Some(b'+') => ("", &text[1..]),
+            _ => ("", text),
+        };
+        let body = body.trim_end_matches(['f', 'F', 'd', 'D']);
+        let mut fixed = String::from(sign);
+        if body.starts_with('.') {
+            fixed.push('0');
+        }
+        fixed.push_str(body);
+        if let Some(point) = fixed.find('.') {
+            if !fixed[point + 1..].starts_with(|c: char| c.is_ascii_digit()) {
+                fixed.insert(point + 1, '0');
+            }
+        }
+
+        if parser::is_number_with(&fixed, &dialect) {
+            str.push_str(&fixed);
+        } else if let Some(int) = parser::decimal_digits(text) {
+            str.push_str(&int);
+        } else {
+            Self::push_float(str, parser::parse_f64(text).unwrap_or(f64::NAN), opts);
+        }
+    }
+
+    fn push_comment(str: &mut String, comment: &Comment) {
+        if comment.block {
+            str.push_str("/*");
+            str.push_str(&comment.text);
+            str.push_str("*/");
+        } else {
+            str.push_str("//");
+            str.push_str(&comment.text);
+        }
+    }
+
+    //the comments that go on the lines before a value, each on its own line
+    fn push_leading(str: &mut String, comments: &[Comment], tab: usize) {
+        for comment in comments.iter().filter(|c| !c.trailing) {
+            Self::push_comment(str, comment);
+            Self::push_indent(str, tab);
+        }
+    }
+
+    //the comments after a value and its comma: the first on the same line, the others below
+    fn push_trailing(str: &mut String, comments: &[Comment], tab: usize) {
+        for (i, comment) in comments.iter().filter(|c| c.trailing).enumerate() {
+            if i == 0 {
+                str.push(' ');
+            } else {
+                Self::push_indent(str, tab);
+            }
+            Self::push_comment(str, comment);
+        }
+    }
+
+    fn push_indent(str: &mut String, tab: usize) {
+        str.push('\n');
+        for _ in 0..tab {
+            str.push('\t');
+        }
+    }
+
+    //objects and arrays are written with a stack of their iterators instead of recursion,
+    //so no depth of nesting can overflow the call stack
     pub(crate) fn str_push_value(
         cxt: &JsonContext,
         str: &mut String,
         value: &JsonValue,
         tab: usize,
-        beautify: bool,
+        opts: &WriteOptions,
     ) {
-        match value.typ {
-            JsonType::Int => str.push_str(&(value.val as i64).to_string()),
-            JsonType::Float => str.push_str(format!("{:?}", &f64::from_bits(value.val)).as_str()),
-            JsonType::Boolean => str.push_str(if value.val == 1 { &"true" } else { &"false" }),
-            JsonType::String => Self::push_string(str, cxt.strings.get(&value.val).unwrap()),
-            JsonType::Null => str.push_str("null"),
-            JsonType::Object => str.push_str(
-                &cxt.objects
-                    .get(&value.val)
-                    .unwrap()
-                    .to_string(cxt, tab, beautify),
-            ),
-            JsonType::Array => {
-                str.push_str("[");
-                let arr = cxt.arrays.get(&value.val).unwrap();
-
-                for val in arr.iter() {
-                    if beautify {
-                        str.push_str("\n");
-                        for _ in 0..(tab + 1) {
-                            str.push_str("\t");
-                        }
+        let mut stack: Vec<WriteFrame> = Vec::new();
+        let mut next = Some(value);
+
+        //comments only fit where there are line breaks
+        let with_comments = opts.beautify && opts.comments;
+        let no_comments: &[Comment] = &[];
+        let root_comments = if with_comments && tab == 0 {
+            cxt.root_comments.as_slice()
+        } else {
+            no_comments
+        };
+        Self::push_leading(str, root_comments, tab);
+
+        loop {
+            if let Some(value) = next.take() {
+                match value.typ {
+                    JsonType::Int => str.push_str(&(value.val as i64).to_string()),
+                    JsonType::UInt => str.push_str(&value.val.to_string()),
+                    JsonType::Number => {
+                        Self::push_number(str, cxt.strings.get(&value.val).unwrap(), opts)
+                    }
+                    JsonType::Float => Self::push_float(str, f64::from_bits(value.val), opts),
+                    JsonType::Boolean => {
+                        str.push_str(if value.val == 1 { "true" } else { "false" })
+                    }
+                    JsonType::String => {
+                        Self::push_string(str, cxt.strings.get(&value.val).unwrap(), opts)
+                    }
+                    JsonType::Null => str.push_str("null"),
+                    JsonType::Object => {
+                        str.push('{');
+                        let obj = cxt.objects.get(&value.val).unwrap();
+                        stack.push(WriteFrame {
+                            items: WriteItems::Object(obj.values.iter(), &obj.comments),
+                            after: no_comments,
+                        });
+                        continue;
+                    }
+                    JsonType::Array => {
+                        str.push('[');
+                        let arr = cxt.arrays.get(&value.val).unwrap();
+                        let comments = cxt.element_comments.get(&value.val);
+                        stack.push(WriteFrame {
+                            items: WriteItems::Array(arr.iter().enumerate(), comments),
+                            after: no_comments,
+                        });
+                        continue;
                     }
-                    JsonObject::str_push_value(cxt, str, val, tab + 1, beautify);
+                }
 
-                    str.push_str(",");
+                if !stack.is_empty() {
+                    str.push(',');
                 }
+            }
 
-                if !arr.is_empty() {
-                    str.pop();
+            let depth = tab + stack.len();
+            let frame = match stack.last_mut() {
+                Some(frame) => frame,
+                None => {
+                    Self::push_trailing(str, root_comments, tab);
+                    return;
                 }
+            };
+
+            match frame.items.next() {
+                Some((key, item, comments)) => {
+                    let comments = if with_comments { comments } else { no_comments };
+                    Self::push_trailing(str, frame.after, depth);
+                    frame.after = comments;
 
-                if beautify {
-                    str.push_str("\n");
-                    for _ in 0..tab {
-                        str.push_str("\t");
+                    if opts.beautify {
+                        Self::push_indent(str, depth);
                     }
-                }
+                    Self::push_leading(str, comments, depth);
 
-                str.push_str("]");
-            }
-        }
-    }
+                    if let Some(key) = key {
+                        if opts.dialect == Dialect::Json5 && parser::is_identifier(key) {
+                            str.push_str(key);
+                        } else {
+                            Self::push_string(str, key, opts);
+                        }
+                        str.push(':');
+                        if opts.beautify {
+                            str.push(' ');
+                        }
+                    }
 
-    fn to_string(&self, cxt: &JsonContext, p_tab: usize, beautify: bool) -> String {
-        let mut tab = p_tab;
-        let mut str = String::new();
+                    next = Some(item);
+                }
+                None => {
+                    let close = frame.items.close();
+                    let after = frame.after;
+                    stack.pop();
 
-        str.push_str("{");
-        tab += 1;
+                    if str.ends_with(',') {
+                        str.pop(); //pop the comma
+                    }
+                    Self::push_trailing(str, after, depth);
+
+                    if opts.beautify {
+                        Self::push_indent(str, depth - 1);
+                    }
 
-        for (key, value) in &self.values {
-            if beautify {
-                str.push_str("\n");
-                for _ in 0..tab {
-                    str.push_str("\t");
+                    str.push(close);
+                    if !stack.is_empty() {
+                        str.push(',');
+                    }
                 }
             }
+        }
+    }
+}
 
-            Self::push_string(&mut str, &key);
-            
-            str.push(':');
-            if beautify {
-                str.push_str(" ");
-            }
+//an object or array str_push_value has opened and not closed yet
+struct WriteFrame<'a> {
+    items: WriteItems<'a>,
+    after: &'a [Comment], //the trailing comments of the last value written
+}
 
-            JsonObject::str_push_value(cxt, &mut str, value, tab, beautify);
-            str.push_str(",");
-        }
+enum WriteItems<'a> {
+    Object(
+        indexmap::map::Iter<'a, String, JsonValue>,
+        &'a HashMap<String, Vec<Comment>>,
+    ),
+    Array(
+        std::iter::Enumerate<std::slice::Iter<'a, JsonValue>>,
+        Option<&'a Vec<Vec<Comment>>>,
+    ),
+}
 
-        if str.chars().last().unwrap() == ',' {
-            str.pop(); //pop the comma
+impl<'a> WriteItems<'a> {
+    //the next key (objects only), value and its comments
+    fn next(&mut self) -> Option<(Option<&'a String>, &'a JsonValue, &'a [Comment])> {
+        match self {
+            WriteItems::Object(iter, comments) => iter.next().map(|(key, val)| {
+                let comments = comments.get(key).map_or(&[][..], |c| c.as_slice());
+                (Some(key), val, comments)
+            }),
+            WriteItems::Array(iter, comments) => iter.next().map(|(idx, val)| {
+                let comments = comments
+                    .and_then(|c| c.get(idx))
+                    .map_or(&[][..], |c| c.as_slice());
+                (None, val, comments)
+            }),
         }
+    }
 
-        if beautify {
-            str.push_str("\n");
-            for _ in 0..(tab - 1) {
-                str.push_str("\t");
-            }
+    fn close(&self) -> char {
+        match self {
+            WriteItems::Object(..) => '}',
+            WriteItems::Array(..) => ']',
         }
-
-        str.push_str("}");
-
-        return str;
     }
 }
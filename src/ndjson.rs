@@ -0,0 +1,169 @@
+use crate::parser::{self, ParseOptions, ParserError, Span};
+use crate::{JsonContext, WriteOptions};
+use std::error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+/**
+* newline-delimited json (https://github.com/ndjson/ndjson-spec): one document per line.
+* blank lines are skipped. a bad record is an error of its own, the ones after it are
+* still read. an io error ends the records.
+*/
+pub struct NdjsonReader<R: BufRead> {
+    reader: R,
+    opts: ParseOptions,
+    record: usize,
+    line: usize,
+    done: bool,
+}
+
+//a record that did not parse. record counts from 0 and skips blank lines, line counts from 1
+#[derive(Debug)]
+pub struct RecordError {
+    pub record: usize,
+    pub line: usize,
+    pub error: ParserError,
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "record {} (line {}): {}",
+            self.record, self.line, self.error
+        )
+    }
+}
+
+impl error::Error for RecordError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+pub fn read_ndjson<R: BufRead>(reader: R, opts: ParseOptions) -> NdjsonReader<R> {
+    NdjsonReader {
+        reader,
+        opts,
+        record: 0,
+        line: 0,
+        done: false,
+    }
+}
+
+pub fn parse_ndjson(str: &str, opts: ParseOptions) -> NdjsonReader<&[u8]> {
+    read_ndjson(str.as_bytes(), opts)
+}
+
+impl<R: BufRead> NdjsonReader<R> {
+    fn error(&mut self, error: ParserError) -> RecordError {
+        let err = RecordError {
+            record: self.record,
+            line: self.line,
+            error,
+        };
+        self.record += 1;
+        err
+    }
+}
+
+impl<R: BufRead> Iterator for NdjsonReader<R> {
+    type Item = Result<(JsonContext, u64), RecordError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut buf = Vec::new();
+
+        while !self.done {
+            buf.clear();
+            let read = self.reader.read_until(b'\n', &mut buf);
+            self.line += 1;
+
+            match read {
+                Ok(0) => self.done = true,
+                Ok(_) => {}
+                Err(e) => {
+                    self.done = true;
+                    let span = Span {
+                        start: 0,
+                        end: 0,
+                        line: 1,
+                        pos: 1,
+                    };
+                    return Some(Err(self.error(ParserError::Io(e, span))));
+                }
+            }
+
+            let line = match std::str::from_utf8(&buf) {
+                Ok(line) => line,
+                Err(e) => {
+                    let start = e.valid_up_to();
+                    let span = Span {
+                        start,
+                        end: start + 1,
+                        line: 1,
+                        pos: buf[..start].iter().filter(|b| (*b & 0xC0) != 0x80).count() + 1,
+                    };
+                    return Some(Err(self.error(ParserError::InvalidUtf8(span))));
+                }
+            };
+
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let parsed = parser::parse_with(line, self.opts);
+            return Some(match parsed {
+                Ok(doc) => {
+                    self.record += 1;
+                    Ok(doc)
+                }
+                Err(e) => Err(self.error(e)),
+            });
+        }
+
+        None
+    }
+}
+
+//writes documents one per line, beautify is always off
+pub struct NdjsonWriter<W: Write> {
+    writer: W,
+    opts: WriteOptions,
+}
+
+impl<W: Write> NdjsonWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self::with_options(writer, WriteOptions::default())
+    }
+
+    pub fn with_options(writer: W, opts: WriteOptions) -> Self {
+        Self {
+            writer,
+            opts: WriteOptions {
+                beautify: false,
+                ..opts
+            },
+        }
+    }
+
+    pub fn write(&mut self, cxt: &JsonContext) -> io::Result<()> {
+        let mut line = cxt.to_string_with(&self.opts);
+        line.push('\n');
+        self.writer.write_all(line.as_bytes())
+    }
+
+    pub fn write_all<'a, I: IntoIterator<Item = &'a JsonContext>>(
+        &mut self,
+        docs: I,
+    ) -> io::Result<()> {
+        for cxt in docs {
+            self.write(cxt)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
@@ -1,44 +1,576 @@
 //the code to parse a string of json into a struct
-use crate::JsonContext;
+use crate::{Comment, CommentSlot, DuplicateKeys, JsonContext, JsonValue};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::error;
+use std::fmt;
+use std::io::{self, Read};
+use std::mem;
 use std::result::Result;
 use std::str::Chars;
 
 /**
-* unexpected character, line, pos
+* unexpected character, span
 */
 #[derive(Debug)]
 pub enum ParserError {
-    EmptyString,
-    KeyExists(String, usize, usize),
-    BadBeginning(String, usize, usize),
-    UnexpectedEOL(usize, usize),
-    UnexpectedEndOfTokens(),
-    ExpectedChar(char, usize, usize),
-    ExpectedWord(String, usize, usize),
-    UnexpectedChar(char, usize, usize),
-    UnexpectedToken(String, usize, usize),
-    StringUnicode(char, usize, usize),
-    StringEscapeChar(char, usize, usize),
-    IntegerInvalidDecimal(char, usize, usize),
-    IntegerInvalidBinary(char, usize, usize),
-    IntegerInvalidOctal(char, usize, usize),
-    IntegerInvalidHex(char, usize, usize),
-    Test,
+    EmptyString(Span),
+    KeyExists(String, Span),
+    BadBeginning(String, Span),
+    UnexpectedEOL(Span),
+    UnexpectedEndOfTokens(Span),
+    ExpectedChar(char, Span),
+    ExpectedWord(String, Span),
+    UnexpectedChar(char, Span),
+    UnexpectedToken(String, Span),
+    StringUnicode(char, Span),
+    StringEscapeChar(char, Span),
+    IntegerInvalidDecimal(char, Span),
+    IntegerInvalidBinary(char, Span),
+    IntegerInvalidOctal(char, Span),
+    IntegerInvalidHex(char, Span),
+    IntegerOverflow(Span),
+    CommentNotAllowed(Span),
+    TrailingCommaNotAllowed(Span),
+    LeadingPlusNotAllowed(Span),
+    RadixNotAllowed(char, Span),
+    FloatSuffixNotAllowed(char, Span),
+    EscapeNotAllowed(char, Span),
+    ControlCharInString(char, Span),
+    LoneSurrogate(u32, Span),
+    InvalidUtf8(Span),
+    Io(io::Error, Span),
+    DepthLimitExceeded(usize, Span),
+    DocumentTooLarge(usize, Span),
+    StringTooLong(usize, Span),
+    TooManyMembers(usize, Span),
+    ArrayTooLong(usize, Span),
+    SingleQuotesNotAllowed(Span),
+    IdentifierKeyNotAllowed(Span),
+    NonFiniteNotAllowed(Span),
+    BareDecimalPointNotAllowed(Span),
+    InvalidEncoding(Encoding, Span),
 }
 
+/**
+* where an error is: the bytes start..end of the input, and the line and position
+* (counted in characters from 1) of start
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub pos: usize,
+}
+
+//the encodings parse_bytes reads, RFC 8259 section 8.1 and RFC 4627 section 3
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Utf32Le,
+    Utf32Be,
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Utf32Le => "utf-32le",
+            Encoding::Utf32Be => "utf-32be",
+        })
+    }
+}
+
+/**
+* the encoding of bytes and the length of its byte order mark. without a mark the zero bytes
+* in the first four tell, json text starts with two ascii characters.
+*/
+pub fn detect_encoding(bytes: &[u8]) -> (Encoding, usize) {
+    match bytes {
+        [0xEF, 0xBB, 0xBF, ..] => (Encoding::Utf8, 3),
+        [0xFF, 0xFE, 0, 0, ..] => (Encoding::Utf32Le, 4),
+        [0, 0, 0xFE, 0xFF, ..] => (Encoding::Utf32Be, 4),
+        [0xFF, 0xFE, ..] => (Encoding::Utf16Le, 2),
+        [0xFE, 0xFF, ..] => (Encoding::Utf16Be, 2),
+        [0, 0, 0, _, ..] => (Encoding::Utf32Be, 0),
+        [_, 0, 0, 0, ..] => (Encoding::Utf32Le, 0),
+        [0, _, ..] => (Encoding::Utf16Be, 0),
+        [_, 0, ..] => (Encoding::Utf16Le, 0),
+        _ => (Encoding::Utf8, 0),
+    }
+}
+
+impl Span {
+    //the `len` bytes at `offset` inside a span that does not cross lines
+    fn sub(&self, offset: usize, len: usize) -> Span {
+        Span {
+            start: self.start + offset,
+            end: self.start + offset + len,
+            line: self.line,
+            pos: self.pos + offset,
+        }
+    }
+}
+
+impl ParserError {
+    /**
+     * where the error is in the input
+     */
+    pub fn span(&self) -> Span {
+        match self {
+            ParserError::EmptyString(span)
+            | ParserError::UnexpectedEOL(span)
+            | ParserError::UnexpectedEndOfTokens(span)
+            | ParserError::IntegerOverflow(span)
+            | ParserError::CommentNotAllowed(span)
+            | ParserError::TrailingCommaNotAllowed(span)
+            | ParserError::LeadingPlusNotAllowed(span)
+            | ParserError::InvalidUtf8(span)
+            | ParserError::SingleQuotesNotAllowed(span)
+            | ParserError::IdentifierKeyNotAllowed(span)
+            | ParserError::NonFiniteNotAllowed(span)
+            | ParserError::BareDecimalPointNotAllowed(span) => *span,
+            ParserError::DepthLimitExceeded(_, span)
+            | ParserError::DocumentTooLarge(_, span)
+            | ParserError::StringTooLong(_, span)
+            | ParserError::TooManyMembers(_, span)
+            | ParserError::ArrayTooLong(_, span)
+            | ParserError::InvalidEncoding(_, span) => *span,
+            ParserError::KeyExists(_, span)
+            | ParserError::BadBeginning(_, span)
+            | ParserError::ExpectedWord(_, span)
+            | ParserError::UnexpectedToken(_, span)
+            | ParserError::Io(_, span) => *span,
+            ParserError::ExpectedChar(_, span)
+            | ParserError::UnexpectedChar(_, span)
+            | ParserError::StringUnicode(_, span)
+            | ParserError::StringEscapeChar(_, span)
+            | ParserError::IntegerInvalidDecimal(_, span)
+            | ParserError::IntegerInvalidBinary(_, span)
+            | ParserError::IntegerInvalidOctal(_, span)
+            | ParserError::IntegerInvalidHex(_, span)
+            | ParserError::RadixNotAllowed(_, span)
+            | ParserError::FloatSuffixNotAllowed(_, span)
+            | ParserError::EscapeNotAllowed(_, span)
+            | ParserError::ControlCharInString(_, span) => *span,
+            ParserError::LoneSurrogate(_, span) => *span,
+        }
+    }
+
+    /**
+     * a one line description of the error, without its position
+     */
+    pub fn message(&self) -> String {
+        match self {
+            ParserError::EmptyString(_) => "the input has no json value".to_string(),
+            ParserError::KeyExists(key, _) => format!("the key \"{key}\" already exists"),
+            ParserError::BadBeginning(tkn, _) => format!("a json value cannot begin with `{tkn}`"),
+            ParserError::UnexpectedEOL(_) => "unexpected end of input".to_string(),
+            ParserError::UnexpectedEndOfTokens(_) => "unexpected end of input".to_string(),
+            ParserError::ExpectedChar(ch, _) => format!("expected `{ch}`"),
+            ParserError::ExpectedWord(word, _) => format!("expected `{word}`"),
+            ParserError::UnexpectedChar(ch, _) => format!("unexpected character {ch:?}"),
+            ParserError::UnexpectedToken(tkn, _) => format!("unexpected `{tkn}`"),
+            ParserError::StringUnicode(ch, _) => {
+                format!("{ch:?} is not a hex digit of a unicode escape")
+            }
+            ParserError::StringEscapeChar(ch, _) => format!("unknown escape `\\{ch}`"),
+            ParserError::IntegerInvalidDecimal(ch, _) => format!("{ch:?} is not a decimal digit"),
+            ParserError::IntegerInvalidBinary(ch, _) => format!("{ch:?} is not a binary digit"),
+            ParserError::IntegerInvalidOctal(ch, _) => format!("{ch:?} is not an octal digit"),
+            ParserError::IntegerInvalidHex(ch, _) => format!("{ch:?} is not a hex digit"),
+            ParserError::IntegerOverflow(_) => "the integer does not fit 64 bits".to_string(),
+            ParserError::CommentNotAllowed(_) => "comments are not allowed".to_string(),
+            ParserError::TrailingCommaNotAllowed(_) => {
+                "trailing commas are not allowed".to_string()
+            }
+            ParserError::LeadingPlusNotAllowed(_) => "a leading `+` is not allowed".to_string(),
+            ParserError::RadixNotAllowed(ch, _) => {
+                format!("radix integers are not allowed (found `0{ch}`)")
+            }
+            ParserError::FloatSuffixNotAllowed(ch, _) => {
+                format!("the float suffix `{ch}` is not allowed")
+            }
+            ParserError::EscapeNotAllowed(ch, _) => format!("the escape `\\{ch}` is not allowed"),
+            ParserError::ControlCharInString(ch, _) => {
+                format!("the control character {ch:?} must be escaped")
+            }
+            ParserError::LoneSurrogate(code, _) => {
+                format!("the surrogate \\u{code:04X} has no other half")
+            }
+            ParserError::InvalidUtf8(_) => "the input is not valid utf-8".to_string(),
+            ParserError::Io(e, _) => format!("could not read the input: {e}"),
+            ParserError::DepthLimitExceeded(max, _) => {
+                format!("objects and arrays are nested deeper than {max}")
+            }
+            ParserError::DocumentTooLarge(max, _) => {
+                format!("the input is longer than {max} bytes")
+            }
+            ParserError::StringTooLong(max, _) => format!("the string is longer than {max} bytes"),
+            ParserError::TooManyMembers(max, _) => {
+                format!("the object has more than {max} members")
+            }
+            ParserError::ArrayTooLong(max, _) => {
+                format!("the array has more than {max} elements")
+            }
+            ParserError::SingleQuotesNotAllowed(_) => {
+                "single-quoted strings are not allowed".to_string()
+            }
+            ParserError::IdentifierKeyNotAllowed(_) => "keys must be quoted".to_string(),
+            ParserError::NonFiniteNotAllowed(_) => {
+                "`Infinity` and `NaN` are not allowed".to_string()
+            }
+            ParserError::BareDecimalPointNotAllowed(_) => {
+                "a decimal point needs digits on both sides".to_string()
+            }
+            ParserError::InvalidEncoding(enc, _) => format!("the input is not valid {enc}"),
+        }
+    }
+
+    /**
+     * a machine-readable code for the kind of error, such as "E0006" for ExpectedChar.
+     * a code is never reused: new variants take the next free number
+     */
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParserError::EmptyString(_) => "E0001",
+            ParserError::KeyExists(..) => "E0002",
+            ParserError::BadBeginning(..) => "E0003",
+            ParserError::UnexpectedEOL(_) => "E0004",
+            ParserError::UnexpectedEndOfTokens(_) => "E0005",
+            ParserError::ExpectedChar(..) => "E0006",
+            ParserError::ExpectedWord(..) => "E0007",
+            ParserError::UnexpectedChar(..) => "E0008",
+            ParserError::UnexpectedToken(..) => "E0009",
+            ParserError::StringUnicode(..) => "E0010",
+            ParserError::StringEscapeChar(..) => "E0011",
+            ParserError::IntegerInvalidDecimal(..) => "E0012",
+            ParserError::IntegerInvalidBinary(..) => "E0013",
+            ParserError::IntegerInvalidOctal(..) => "E0014",
+            ParserError::IntegerInvalidHex(..) => "E0015",
+            ParserError::IntegerOverflow(_) => "E0016",
+            ParserError::CommentNotAllowed(_) => "E0017",
+            ParserError::TrailingCommaNotAllowed(_) => "E0018",
+            ParserError::LeadingPlusNotAllowed(_) => "E0019",
+            ParserError::RadixNotAllowed(..) => "E0020",
+            ParserError::FloatSuffixNotAllowed(..) => "E0021",
+            ParserError::EscapeNotAllowed(..) => "E0022",
+            ParserError::ControlCharInString(..) => "E0023",
+            ParserError::LoneSurrogate(..) => "E0024",
+            ParserError::InvalidUtf8(_) => "E0025",
+            ParserError::Io(..) => "E0026",
+            ParserError::DepthLimitExceeded(..) => "E0027",
+            ParserError::DocumentTooLarge(..) => "E0028",
+            ParserError::StringTooLong(..) => "E0029",
+            ParserError::TooManyMembers(..) => "E0030",
+            ParserError::ArrayTooLong(..) => "E0031",
+            ParserError::SingleQuotesNotAllowed(_) => "E0032",
+            ParserError::IdentifierKeyNotAllowed(_) => "E0033",
+            ParserError::NonFiniteNotAllowed(_) => "E0034",
+            ParserError::BareDecimalPointNotAllowed(_) => "E0035",
+            ParserError::InvalidEncoding(..) => "E0036",
+        }
+    }
+
+    //the input cannot be read past these, so there is nothing to recover
+    fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            ParserError::UnexpectedEOL(_)
+                | ParserError::UnexpectedEndOfTokens(_)
+                | ParserError::InvalidUtf8(_)
+                | ParserError::InvalidEncoding(..)
+                | ParserError::Io(..)
+                | ParserError::DocumentTooLarge(..)
+                | ParserError::StringTooLong(..)
+        )
+    }
+
+    /**
+     * the error as a rustc-like snippet of `source`, the input that failed to parse:
+     * the message, the line it is on and a `^` under the characters of the span
+     */
+    pub fn render(&self, source: &str) -> String {
+        let span = self.span();
+        let line_num = span.line.to_string();
+        let pad = " ".repeat(line_num.len());
+
+        let mut str = format!("error[{}]: {}\n", self.code(), self.message());
+        str.push_str(&format!("{pad}--> {}:{}\n", span.line, span.pos));
+        str.push_str(&format!("{pad} |\n"));
+
+        //the byte offset of the line the span begins on
+        let line_start = source
+            .split_inclusive('\n')
+            .take(span.line - 1)
+            .map(str::len)
+            .sum::<usize>()
+            .min(source.len());
+        let rest = &source[line_start..];
+        let text = rest.split('\n').next().unwrap_or("");
+        let text = text.strip_suffix('\r').unwrap_or(text);
+        str.push_str(&format!("{line_num} | {text}\n"));
+
+        let start = span.start.clamp(line_start, line_start + text.len()) - line_start;
+        let end = span.end.clamp(line_start + start, line_start + text.len()) - line_start;
+        let (before, under) = match (text.get(..start), text.get(start..end)) {
+            (Some(before), Some(under)) => (before, under),
+            _ => (text, ""),
+        };
+
+        str.push_str(&format!("{pad} | "));
+        for ch in before.chars() {
+            str.push(if ch == '\t' { '\t' } else { ' ' });
+        }
+        str.push_str(&"^".repeat(under.chars().count().max(1)));
+
+        str
+    }
+}
+
+impl fmt::Display for ParserError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let span = self.span();
+        write!(
+            f,
+            "{}: {} at line {}, position {}",
+            self.code(),
+            self.message(),
+            span.line,
+            span.pos
+        )
+    }
+}
+
+//an io error is copied as its kind and message, io::Error itself cannot be cloned
+impl Clone for ParserError {
+    fn clone(&self) -> Self {
+        match self {
+            ParserError::EmptyString(span) => ParserError::EmptyString(*span),
+            ParserError::KeyExists(a, span) => ParserError::KeyExists(a.clone(), *span),
+            ParserError::BadBeginning(a, span) => ParserError::BadBeginning(a.clone(), *span),
+            ParserError::UnexpectedEOL(span) => ParserError::UnexpectedEOL(*span),
+            ParserError::UnexpectedEndOfTokens(span) => ParserError::UnexpectedEndOfTokens(*span),
+            ParserError::ExpectedChar(a, span) => ParserError::ExpectedChar(*a, *span),
+            ParserError::ExpectedWord(a, span) => ParserError::ExpectedWord(a.clone(), *span),
+            ParserError::UnexpectedChar(a, span) => ParserError::UnexpectedChar(*a, *span),
+            ParserError::UnexpectedToken(a, span) => ParserError::UnexpectedToken(a.clone(), *span),
+            ParserError::StringUnicode(a, span) => ParserError::StringUnicode(*a, *span),
+            ParserError::StringEscapeChar(a, span) => ParserError::StringEscapeChar(*a, *span),
+            ParserError::IntegerInvalidDecimal(a, span) => {
+                ParserError::IntegerInvalidDecimal(*a, *span)
+            }
+            ParserError::IntegerInvalidBinary(a, span) => {
+                ParserError::IntegerInvalidBinary(*a, *span)
+            }
+            ParserError::IntegerInvalidOctal(a, span) => {
+                ParserError::IntegerInvalidOctal(*a, *span)
+            }
+            ParserError::IntegerInvalidHex(a, span) => ParserError::IntegerInvalidHex(*a, *span),
+            ParserError::IntegerOverflow(span) => ParserError::IntegerOverflow(*span),
+            ParserError::CommentNotAllowed(span) => ParserError::CommentNotAllowed(*span),
+            ParserError::TrailingCommaNotAllowed(span) => {
+                ParserError::TrailingCommaNotAllowed(*span)
+            }
+            ParserError::LeadingPlusNotAllowed(span) => ParserError::LeadingPlusNotAllowed(*span),
+            ParserError::RadixNotAllowed(a, span) => ParserError::RadixNotAllowed(*a, *span),
+            ParserError::FloatSuffixNotAllowed(a, span) => {
+                ParserError::FloatSuffixNotAllowed(*a, *span)
+            }
+            ParserError::EscapeNotAllowed(a, span) => ParserError::EscapeNotAllowed(*a, *span),
+            ParserError::ControlCharInString(a, span) => {
+                ParserError::ControlCharInString(*a, *span)
+            }
+            ParserError::LoneSurrogate(a, span) => ParserError::LoneSurrogate(*a, *span),
+            ParserError::InvalidUtf8(span) => ParserError::InvalidUtf8(*span),
+            ParserError::Io(e, span) => {
+                ParserError::Io(io::Error::new(e.kind(), e.to_string()), *span)
+            }
+            ParserError::DepthLimitExceeded(a, span) => ParserError::DepthLimitExceeded(*a, *span),
+            ParserError::DocumentTooLarge(a, span) => ParserError::DocumentTooLarge(*a, *span),
+            ParserError::StringTooLong(a, span) => ParserError::StringTooLong(*a, *span),
+            ParserError::TooManyMembers(a, span) => ParserError::TooManyMembers(*a, *span),
+            ParserError::ArrayTooLong(a, span) => ParserError::ArrayTooLong(*a, *span),
+            ParserError::SingleQuotesNotAllowed(span) => ParserError::SingleQuotesNotAllowed(*span),
+            ParserError::IdentifierKeyNotAllowed(span) => {
+                ParserError::IdentifierKeyNotAllowed(*span)
+            }
+            ParserError::NonFiniteNotAllowed(span) => ParserError::NonFiniteNotAllowed(*span),
+            ParserError::BareDecimalPointNotAllowed(span) => {
+                ParserError::BareDecimalPointNotAllowed(*span)
+            }
+            ParserError::InvalidEncoding(a, span) => ParserError::InvalidEncoding(*a, *span),
+        }
+    }
+}
+
+impl error::Error for ParserError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ParserError::Io(e, _) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/**
+* the extensions to standard json (RFC 8259) that the parser accepts.
+* a disabled extension is reported with its own ParserError variant.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseOptions {
+    pub comments: bool,            // `// line` and `/* block */`
+    pub trailing_commas: bool,     // `[1, 2,]` and `{"a": 1,}`
+    pub leading_plus: bool,        // `+1`
+    pub radix_integers: bool,      // `0x1F`, `0o17`, `017`, `0b101`
+    pub float_suffixes: bool,      // `1.5f`, `2.0F`, `3d`, `4D`
+    pub extended_escapes: bool,    // `\'`, `\v`, `\0`, `\xFF`, `\q`, no digit after `\0` or as q
+    pub control_chars: bool,       // raw U+0000 to U+001F inside strings, but for line breaks
+    pub raw_line_breaks: bool,     // raw `\n` and `\r` inside strings
+    pub hex_integers: bool,        // `0x1F` alone, radix_integers includes it
+    pub identifier_keys: bool,     // `{key: 1}`
+    pub single_quotes: bool,       // `'text'`
+    pub non_finite_numbers: bool,  // `Infinity`, `-Infinity`, `NaN`
+    pub bare_decimal_points: bool, // `.5`, `5.`
+    pub line_continuations: bool,  // a `\` right before a line break inside a string
+    pub unicode_whitespace: bool,  // U+000B, U+000C, U+00A0, U+FEFF and other space separators
+
+    //not an extension, keeps the text of every number as JsonType::Number so no digits
+    //are lost to i64/f64. both presets leave it off.
+    pub preserve_numbers: bool,
+
+    //writes U+FFFD for a \u escape of half a surrogate pair instead of failing with
+    //ParserError::LoneSurrogate. both presets leave it off.
+    pub replace_lone_surrogates: bool,
+
+    //how deep objects and arrays may nest, the root being 1. deeper input fails with
+    //ParserError::DepthLimitExceeded. both presets allow 128.
+    pub max_depth: usize,
+
+    //what a key that repeats in an object does, both presets fail with ParserError::KeyExists
+    pub duplicate_keys: DuplicateKeys,
+
+    //limits on what untrusted input can make the parser allocate. each fails with its own
+    //ParserError as soon as it is passed. both presets leave them at usize::MAX, no limit.
+    pub max_document_size: usize, //bytes of input
+    pub max_string_length: usize, //bytes of a string or key, after escapes
+    pub max_object_members: usize,
+    pub max_array_length: usize,
+}
+
+impl ParseOptions {
+    pub fn strict() -> Self {
+        Self {
+            comments: false,
+            trailing_commas: false,
+            leading_plus: false,
+            radix_integers: false,
+            float_suffixes: false,
+            extended_escapes: false,
+            control_chars: false,
+            raw_line_breaks: false,
+            hex_integers: false,
+            identifier_keys: false,
+            single_quotes: false,
+            non_finite_numbers: false,
+            bare_decimal_points: false,
+            line_continuations: false,
+            unicode_whitespace: false,
+            preserve_numbers: false,
+            replace_lone_surrogates: false,
+            max_depth: 128,
+            duplicate_keys: DuplicateKeys::Error,
+            max_document_size: usize::MAX,
+            max_string_length: usize::MAX,
+            max_object_members: usize::MAX,
+            max_array_length: usize::MAX,
+        }
+    }
+
+    pub fn relaxed() -> Self {
+        Self {
+            comments: true,
+            trailing_commas: true,
+            leading_plus: true,
+            radix_integers: true,
+            float_suffixes: true,
+            extended_escapes: true,
+            control_chars: true,
+            raw_line_breaks: true,
+            hex_integers: true,
+            identifier_keys: false,
+            single_quotes: false,
+            non_finite_numbers: false,
+            bare_decimal_points: false,
+            line_continuations: false,
+            unicode_whitespace: false,
+            preserve_numbers: false,
+            replace_lone_surrogates: false,
+            max_depth: 128,
+            duplicate_keys: DuplicateKeys::Error,
+            max_document_size: usize::MAX,
+            max_string_length: usize::MAX,
+            max_object_members: usize::MAX,
+            max_array_length: usize::MAX,
+        }
+    }
+
+    //JSON5 (https://spec.json5.org): relaxed without the radix and float suffix extensions
+    //and without line breaks inside strings unless they are escaped, but with identifier keys,
+    //single quotes, Infinity and NaN, bare decimal points, line continuations and unicode
+    //whitespace, which only this dialect has
+    pub fn json5() -> Self {
+        Self {
+            radix_integers: false,
+            float_suffixes: false,
+            raw_line_breaks: false,
+            identifier_keys: true,
+            single_quotes: true,
+            non_finite_numbers: true,
+            bare_decimal_points: true,
+            line_continuations: true,
+            unicode_whitespace: true,
+            ..Self::relaxed()
+        }
+    }
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self::relaxed()
+    }
+}
+
+enum Number {
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+}
+
+#[derive(Clone, Copy)]
 struct Position {
     line: usize,
     pos: usize,
+    offset: usize, //in bytes
 }
 
 impl Position {
     fn new() -> Self {
-        Self { line: 1, pos: 0 }
+        Self {
+            line: 1,
+            pos: 0,
+            offset: 0,
+        }
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
-enum TokenType {
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub(crate) enum TokenType {
     OBrk,  //{
     CBrk,  //}
     OSBrk, //[
@@ -50,862 +582,2657 @@ enum TokenType {
     Null,
     Number,
     String,
+    Ident, //a key without quotes
+}
+
+#[derive(Debug)]
+pub(crate) struct Token {
+    pub(crate) value: String, //strings and keys decoded, a number's text stays in the lexer
+    pub(crate) typ: TokenType,
+    pub(crate) span: Span,
+}
+
+impl Token {
+    fn new(value: String, typ: TokenType, span: Span) -> Self {
+        Self { value, typ, span }
+    }
+
+    //punctuation and true, false and null carry no text, reading them does not allocate
+    fn new_empty(typ: TokenType, span: Span) -> Self {
+        Self::new(String::new(), typ, span)
+    }
+
+    fn word(&self) -> Option<&'static str> {
+        match self.typ {
+            TokenType::OBrk => Some("{"),
+            TokenType::CBrk => Some("}"),
+            TokenType::OSBrk => Some("["),
+            TokenType::CSBrk => Some("]"),
+            TokenType::Colon => Some(":"),
+            TokenType::Comma => Some(","),
+            TokenType::True => Some("true"),
+            TokenType::False => Some("false"),
+            TokenType::Null => Some("null"),
+            _ => None,
+        }
+    }
+
+    //the token as it reads, for keys and errors
+    pub(crate) fn text(&self) -> String {
+        match self.word() {
+            Some(word) => word.to_string(),
+            None => self.value.clone(),
+        }
+    }
+
+    pub(crate) fn into_text(self) -> String {
+        match self.word() {
+            Some(word) => word.to_string(),
+            None => self.value,
+        }
+    }
+}
+
+const READ_BUFFER_SIZE: usize = 8192;
+
+enum SourceError {
+    Io(io::Error),
+    Utf8,
+}
+
+//where the lexer pulls its characters from
+trait Source {
+    fn next(&mut self) -> Result<Option<char>, SourceError>;
+    fn peek(&mut self) -> Result<Option<char>, SourceError>;
+
+    //the input ran out but more is coming, see PushSource
+    fn starved(&self) -> bool {
+        false
+    }
+
+    //the characters up to the next quote, `\` or control character, so strings can be
+    //copied a run at a time. empty if the source cannot look that far ahead
+    fn plain_run(&self, _quote: u8) -> &str {
+        ""
+    }
+
+    //the spaces, tabs and line breaks ahead, the same way
+    fn blank_run(&self) -> &str {
+        ""
+    }
+
+    //the ascii letters, digits and `.+-` ahead, the same way
+    fn word_run(&self) -> &str {
+        ""
+    }
+
+    //moves past len bytes of a run
+    fn skip_run(&mut self, _len: usize) {}
+}
+
+//the length of the utf-8 sequence that starts with first
+fn utf8_width(first: u8) -> Result<usize, SourceError> {
+    match first {
+        0x00..=0x7F => Ok(1),
+        0xC2..=0xDF => Ok(2),
+        0xE0..=0xEF => Ok(3),
+        0xF0..=0xF4 => Ok(4),
+        _ => Err(SourceError::Utf8),
+    }
+}
+
+//the length of the string content at the start of bytes: up to the closing quote, a `\` or
+//a control character
+fn string_run(bytes: &[u8], quote: u8) -> usize {
+    let stop = |b: &u8| *b == quote || *b == b'\\' || *b < 0x20;
+    bytes.iter().position(stop).unwrap_or(bytes.len())
+}
+
+//the length of the spaces, tabs and line breaks at the start of bytes
+fn blank_run(bytes: &[u8]) -> usize {
+    let stop = |b: &u8| !matches!(b, b' ' | b'\t' | b'\r' | b'\n');
+    bytes.iter().position(stop).unwrap_or(bytes.len())
+}
+
+//the length of the ascii letters, digits and `.+-` at the start of bytes, what a number is
+//made of
+fn word_run(bytes: &[u8]) -> usize {
+    let stop = |b: &u8| !(b.is_ascii_alphanumeric() || matches!(b, b'.' | b'+' | b'-'));
+    bytes.iter().position(stop).unwrap_or(bytes.len())
+}
+
+struct StrSource<'a> {
+    chars: Chars<'a>,
+}
+
+impl<'a> StrSource<'a> {
+    fn new(str: &'a str) -> Self {
+        Self { chars: str.chars() }
+    }
+}
+
+impl Source for StrSource<'_> {
+    fn next(&mut self) -> Result<Option<char>, SourceError> {
+        Ok(self.chars.next())
+    }
+
+    fn peek(&mut self) -> Result<Option<char>, SourceError> {
+        Ok(self.chars.clone().next())
+    }
+
+    fn plain_run(&self, quote: u8) -> &str {
+        let rest = self.chars.as_str();
+        &rest[..string_run(rest.as_bytes(), quote)]
+    }
+
+    fn blank_run(&self) -> &str {
+        let rest = self.chars.as_str();
+        &rest[..blank_run(rest.as_bytes())]
+    }
+
+    fn word_run(&self) -> &str {
+        let rest = self.chars.as_str();
+        &rest[..word_run(rest.as_bytes())]
+    }
+
+    fn skip_run(&mut self, len: usize) {
+        self.chars = self.chars.as_str()[len..].chars();
+    }
+}
+
+//decodes utf-8 from a reader through a fixed size buffer
+struct ReadSource<R: Read> {
+    reader: R,
+    buf: Box<[u8]>,
+    start: usize,
+    end: usize,
+    peeked: Option<char>,
+}
+
+impl<R: Read> ReadSource<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buf: vec![0u8; READ_BUFFER_SIZE].into_boxed_slice(),
+            start: 0,
+            end: 0,
+            peeked: None,
+        }
+    }
+
+    //makes sure at least `need` bytes are buffered, false at the end of the input
+    fn fill(&mut self, need: usize) -> Result<bool, SourceError> {
+        if self.end - self.start >= need {
+            return Ok(true);
+        }
+
+        self.buf.copy_within(self.start..self.end, 0);
+        self.end -= self.start;
+        self.start = 0;
+
+        while self.end < need {
+            match self.reader.read(&mut self.buf[self.end..]) {
+                Ok(0) => return Ok(false),
+                Ok(n) => self.end += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(SourceError::Io(e)),
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn decode(&mut self) -> Result<Option<char>, SourceError> {
+        if !self.fill(1)? {
+            return Ok(None);
+        }
+
+        let width = utf8_width(self.buf[self.start])?;
+
+        if !self.fill(width)? {
+            return Err(SourceError::Utf8);
+        }
+
+        match std::str::from_utf8(&self.buf[self.start..self.start + width]) {
+            Ok(s) => {
+                self.start += width;
+                Ok(s.chars().next())
+            }
+            Err(_) => Err(SourceError::Utf8),
+        }
+    }
+
+    //the first len buffered bytes, up to the last whole character. only what is buffered
+    //already, the rest comes character by character
+    fn buffered(&self, len: usize) -> &str {
+        if self.peeked.is_some() {
+            return "";
+        }
+
+        let bytes = &self.buf[self.start..self.start + len];
+        match std::str::from_utf8(bytes) {
+            Ok(run) => run,
+            Err(e) => std::str::from_utf8(&bytes[..e.valid_up_to()]).unwrap(),
+        }
+    }
+}
+
+impl<R: Read> Source for ReadSource<R> {
+    fn next(&mut self) -> Result<Option<char>, SourceError> {
+        match self.peeked.take() {
+            Some(c) => Ok(Some(c)),
+            None => self.decode(),
+        }
+    }
+
+    fn peek(&mut self) -> Result<Option<char>, SourceError> {
+        if self.peeked.is_none() {
+            self.peeked = self.decode()?;
+        }
+
+        Ok(self.peeked)
+    }
+
+    fn plain_run(&self, quote: u8) -> &str {
+        let len = string_run(&self.buf[self.start..self.end], quote);
+        self.buffered(len)
+    }
+
+    fn blank_run(&self) -> &str {
+        let len = blank_run(&self.buf[self.start..self.end]);
+        self.buffered(len)
+    }
+
+    fn word_run(&self) -> &str {
+        let len = word_run(&self.buf[self.start..self.end]);
+        self.buffered(len)
+    }
+
+    fn skip_run(&mut self, len: usize) {
+        self.start += len;
+    }
+}
+
+//the chunks given to PushParser::feed. running out before finish is not the end of the
+//input, the source is starved and the parser waits for the next chunk
+struct PushSource {
+    buf: Vec<u8>,
+    start: usize,
+    finished: bool,
+    starved: bool,
+}
+
+impl PushSource {
+    fn decode(&mut self) -> Result<Option<(char, usize)>, SourceError> {
+        let rest = &self.buf[self.start..];
+        let width = match rest.first() {
+            Some(first) => utf8_width(*first)?,
+            None => {
+                self.starved |= !self.finished;
+                return Ok(None);
+            }
+        };
+
+        //a code point split between two chunks
+        if rest.len() < width {
+            if self.finished {
+                return Err(SourceError::Utf8);
+            }
+
+            self.starved = true;
+            return Ok(None);
+        }
+
+        match std::str::from_utf8(&rest[..width]) {
+            Ok(s) => Ok(s.chars().next().map(|c| (c, width))),
+            Err(_) => Err(SourceError::Utf8),
+        }
+    }
+}
+
+impl Source for PushSource {
+    fn next(&mut self) -> Result<Option<char>, SourceError> {
+        let decoded = self.decode()?;
+        if let Some((_, width)) = decoded {
+            self.start += width;
+        }
+
+        Ok(decoded.map(|(c, _)| c))
+    }
+
+    fn peek(&mut self) -> Result<Option<char>, SourceError> {
+        Ok(self.decode()?.map(|(c, _)| c))
+    }
+
+    fn starved(&self) -> bool {
+        self.starved
+    }
+}
+
+//turns the characters of a source into tokens, one at a time
+struct Lexer<S: Source> {
+    src: S,
+    pos: Position,
+    last: Span,             //the character bump returned last
+    pending: Option<Token>, //a token given back with unpop
+    opts: ParseOptions,
+    errors: Option<Vec<ParserError>>, //Some when recovering from errors
+    comments: Vec<Comment>,           //read since the parser last took them
+    keep_comments: bool,
+    token_line: usize,          //the line the last token ended on
+    token_start: Option<usize>, //the offset of a token that is not complete yet
+    scratch: String,            //the word or number being read, kept between tokens
+    skim: bool,                 //strings are checked but not kept, for skipping values
+}
+
+//what a string decodes to, or only its length when the lexer skims
+struct Decoded {
+    text: String,
+    len: usize,
+    keep: bool,
+}
+
+impl Decoded {
+    fn new(keep: bool) -> Self {
+        Self {
+            text: String::new(),
+            len: 0,
+            keep,
+        }
+    }
+
+    fn push(&mut self, ch: char) {
+        self.len += ch.len_utf8();
+        if self.keep {
+            self.text.push(ch);
+        }
+    }
+
+    fn push_str(&mut self, str: &str) {
+        self.len += str.len();
+        if self.keep {
+            self.text.push_str(str);
+        }
+    }
+}
+
+impl<S: Source> Lexer<S> {
+    fn new(src: S, opts: ParseOptions) -> Self {
+        let pos = Position::new();
+        let last = Span {
+            start: 0,
+            end: 0,
+            line: 1,
+            pos: 0,
+        };
+
+        Self {
+            src,
+            pos,
+            last,
+            pending: None,
+            opts,
+            errors: None,
+            comments: Vec::new(),
+            keep_comments: true,
+            token_line: 1,
+            token_start: None,
+            scratch: String::new(),
+            skim: false,
+        }
+    }
+
+    //when recovering, keeps the error and carries on. otherwise it is returned
+    fn report(&mut self, err: ParserError) -> Result<(), ParserError> {
+        match &mut self.errors {
+            Some(errors) => {
+                //one mistake often upsets the next token too, only keep the first
+                if errors.last().map(|e| e.span().start) != Some(err.span().start) {
+                    errors.push(err);
+                }
+                Ok(())
+            }
+            None => Err(err),
+        }
+    }
+
+    //reports the error, then skips to the next `,`, `}` or `]` of the current object or array.
+    //open are the objects and arrays being parsed
+    fn recover(&mut self, err: ParserError, open: &[Frame]) -> Result<(), ParserError> {
+        if err.is_fatal() {
+            return Err(err);
+        }
+
+        //the `{` or `[` that went too deep was read already, skip what it holds too
+        let mut depth = match err {
+            ParserError::DepthLimitExceeded(..) => 1,
+            _ => 0,
+        };
+        self.report(err)?;
+
+        loop {
+            let tkn = match self.pop() {
+                Ok(tkn) => tkn,
+                Err(e) if e.is_fatal() => return Err(e),
+                Err(e) => {
+                    self.report(e)?;
+                    continue;
+                }
+            };
+
+            match tkn.typ {
+                TokenType::OBrk | TokenType::OSBrk => depth += 1,
+                //a closer that matches nothing open is skipped
+                TokenType::CBrk | TokenType::CSBrk
+                    if depth == 0 && !open.iter().any(|f| f.close == tkn.typ) => {}
+                TokenType::CBrk | TokenType::CSBrk | TokenType::Comma if depth == 0 => {
+                    self.unpop(tkn);
+                    return Ok(());
+                }
+                TokenType::CBrk | TokenType::CSBrk => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+
+    fn unpop(&mut self, tkn: Token) {
+        self.pending = Some(tkn);
+    }
+
+    //the next `len` bytes
+    fn span_next(&self, len: usize) -> Span {
+        Span {
+            start: self.pos.offset,
+            end: self.pos.offset + len,
+            line: self.pos.line,
+            pos: self.pos.pos + 1,
+        }
+    }
+
+    //from the start of `from` up to everything bumped so far
+    fn span_from(&self, from: Span) -> Span {
+        Span {
+            end: self.pos.offset,
+            ..from
+        }
+    }
+
+    fn source_err(&self, e: SourceError) -> ParserError {
+        match e {
+            SourceError::Io(e) => ParserError::Io(e, self.span_next(0)),
+            SourceError::Utf8 => ParserError::InvalidUtf8(self.span_next(1)),
+        }
+    }
+
+    fn eol(&self) -> ParserError {
+        ParserError::UnexpectedEOL(self.span_next(0))
+    }
+
+    fn bump(&mut self) -> Result<Option<char>, ParserError> {
+        let c = self.src.next().map_err(|e| self.source_err(e))?;
+
+        if let Some(c) = c {
+            if self.pos.offset + c.len_utf8() > self.opts.max_document_size {
+                let span = self.span_next(c.len_utf8());
+                return Err(ParserError::DocumentTooLarge(
+                    self.opts.max_document_size,
+                    span,
+                ));
+            }
+
+            self.last = self.span_next(c.len_utf8());
+            self.pos.offset += c.len_utf8();
+
+            if c == '\n' {
+                self.pos.line += 1;
+                self.pos.pos = 0;
+            } else {
+                self.pos.pos += 1;
+            }
+        }
+
+        Ok(c)
+    }
+
+    fn peek(&mut self) -> Result<Option<char>, ParserError> {
+        self.src.peek().map_err(|e| self.source_err(e))
+    }
+
+    fn eat_whitespace(&mut self) -> Result<Option<char>, ParserError> {
+        loop {
+            self.skip_blanks();
+            match self.bump()? {
+                Some(' ' | '\t' | '\r' | '\n') => {}
+                Some(c) if self.opts.unicode_whitespace && is_unicode_space(c) => {}
+                c => return Ok(c),
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Result<Token, ParserError> {
+        match self.next_token()? {
+            Some(token) => Ok(token),
+            None => Err(ParserError::UnexpectedEndOfTokens(self.span_next(0))),
+        }
+    }
+
+    //the token as it reads. a number, or an identifier while skimming, is not copied out of
+    //scratch, it is there until the next token is read
+    fn text(&self, tkn: &Token) -> String {
+        match tkn.typ {
+            TokenType::Number => self.scratch.clone(),
+            TokenType::Ident if self.skim => self.scratch.clone(),
+            _ => tkn.text(),
+        }
+    }
+
+    fn take_text(&self, tkn: Token) -> String {
+        match tkn.typ {
+            TokenType::Number => self.scratch.clone(),
+            TokenType::Ident if self.skim => self.scratch.clone(),
+            _ => tkn.into_text(),
+        }
+    }
+
+    fn expect(&mut self, expect: TokenType) -> Result<Token, ParserError> {
+        let token = self.pop()?;
+        if token.typ != expect {
+            let err = ParserError::UnexpectedToken(self.text(&token), token.span);
+            self.unpop(token);
+            return Err(err);
+        }
+
+        Ok(token)
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token>, ParserError> {
+        if let Some(tkn) = self.pending.take() {
+            return Ok(Some(tkn));
+        }
+
+        let tkn = self.lex_token()?;
+        if self.src.starved() {
+            //the token may go on in the next chunk
+            return Err(self.eol());
+        }
+
+        self.token_start = None;
+        self.token_line = self.pos.line;
+        Ok(tkn)
+    }
+
+    fn lex_token(&mut self) -> Result<Option<Token>, ParserError> {
+        loop {
+            let ch = match self.eat_whitespace()? {
+                Some(ch) => ch,
+                None => return Ok(None),
+            };
+
+            let start = self.last;
+            self.token_start = Some(start.start);
+            let typ = match ch {
+                '{' => TokenType::OBrk,
+                '}' => TokenType::CBrk,
+                '[' => TokenType::OSBrk,
+                ']' => TokenType::CSBrk,
+                ':' => TokenType::Colon,
+                ',' => TokenType::Comma,
+                '\"' | '\'' => {
+                    if ch == '\'' && !self.opts.single_quotes {
+                        self.report(ParserError::SingleQuotesNotAllowed(start))?;
+                    }
+
+                    let str = self.parse_string(start, ch)?;
+                    return Ok(Some(Token::new(
+                        str,
+                        TokenType::String,
+                        self.span_from(start),
+                    )));
+                }
+                '/' => {
+                    if !self.opts.comments {
+                        self.report(ParserError::CommentNotAllowed(start))?;
+                    }
+
+                    let mut comment = self.lex_comment(start)?;
+                    self.token_start = None;
+                    if self.keep_comments {
+                        comment.trailing = start.line == self.token_line;
+                        self.comments.push(comment);
+                    }
+                    continue;
+                }
+                _ if is_ident_start(ch) || (ch == '\\' && self.opts.identifier_keys) => {
+                    self.lex_ident(ch, start)?;
+                    let span = self.span_from(start);
+                    let typ = match self.scratch.as_str() {
+                        "true" => TokenType::True,
+                        "false" => TokenType::False,
+                        "null" => TokenType::Null,
+                        "Infinity" | "NaN" => TokenType::Number,
+                        _ => TokenType::Ident,
+                    };
+
+                    //an identifier can only be a key, it keeps the buffer unless the lexer
+                    //skims. Infinity and NaN are left in scratch like other numbers
+                    return Ok(Some(match typ {
+                        TokenType::Ident if !self.skim => {
+                            Token::new(mem::take(&mut self.scratch), typ, span)
+                        }
+                        _ => Token::new_empty(typ, span),
+                    }));
+                }
+                _ => {
+                    if ch.is_ascii_digit() || ch == '-' || ch == '+' || ch == '.' {
+                        self.scratch.clear();
+                        self.scratch.push(ch);
+                        self.copy_word();
+
+                        while let Some(c) = self.peek()? {
+                            if !(c.is_alphanumeric() || c == '.' || c == '+' || c == '-') {
+                                break;
+                            }
+
+                            self.bump()?;
+                            self.scratch.push(c);
+                        }
+
+                        let span = self.span_from(start);
+                        return Ok(Some(Token::new_empty(TokenType::Number, span)));
+                    } else {
+                        return Err(ParserError::UnexpectedChar(ch, start));
+                    }
+                }
+            };
+
+            return Ok(Some(Token::new_empty(typ, self.span_from(start))));
+        }
+    }
+
+    //reads the rest of an identifier, true, false and null included. `\u0061` escapes are
+    //decoded, scratch is left with what the identifier means
+    fn lex_ident(&mut self, first: char, start: Span) -> Result<(), ParserError> {
+        self.scratch.clear();
+        let mut ch = first;
+
+        loop {
+            if ch == '\\' {
+                let esc_start = self.last;
+                match self.bump()? {
+                    Some('u') => {}
+                    Some(c) => {
+                        let span = self.span_from(esc_start);
+                        return Err(ParserError::StringEscapeChar(c, span));
+                    }
+                    None => return Err(self.eol()),
+                }
+
+                let mut code = 0u32;
+                for _ in 0..4 {
+                    match self.bump()? {
+                        Some(x) => match x.to_digit(16) {
+                            Some(digit) => code = (code << 4) | digit,
+                            None => return Err(ParserError::StringUnicode(x, self.last)),
+                        },
+                        None => return Err(self.eol()),
+                    }
+                }
+
+                match char::from_u32(code) {
+                    Some(c) if is_ident_part(c) => self.scratch.push(c),
+                    _ => {
+                        let span = self.span_from(esc_start);
+                        let word = self.scratch.clone();
+                        return Err(ParserError::UnexpectedToken(word, span));
+                    }
+                }
+            } else {
+                self.scratch.push(ch);
+            }
+
+            match self.peek()? {
+                Some(c) if is_ident_part(c) || (c == '\\' && self.opts.identifier_keys) => {
+                    self.bump()?;
+                    ch = c;
+                }
+                _ => break,
+            }
+        }
+
+        if self.scratch.is_empty() {
+            return Err(ParserError::UnexpectedChar(first, start));
+        }
+
+        Ok(())
+    }
+
+    //trailing is left for the caller to set
+    fn lex_comment(&mut self, start: Span) -> Result<Comment, ParserError> {
+        let mut text = String::new();
+        let block = match self.bump()? {
+            Some('/') => {
+                while let Some(ch) = self.bump()? {
+                    if ch == '\n' {
+                        break;
+                    }
+                    text.push(ch);
+                }
+
+                if text.ends_with('\r') {
+                    text.pop();
+                }
+                false
+            }
+            Some('*') => {
+                let mut star = false;
+                loop {
+                    match self.bump()? {
+                        Some(ch) => {
+                            if star && ch == '/' {
+                                text.pop(); //the `*`
+                                break;
+                            }
+                            star = ch == '*';
+                            text.push(ch);
+                        }
+                        None => {
+                            return Err(ParserError::UnexpectedEndOfTokens(self.span_from(start)));
+                        }
+                    }
+                }
+                true
+            }
+            Some(ch) => {
+                return Err(ParserError::UnexpectedChar(ch, self.last));
+            }
+            None => {
+                return Err(self.eol());
+            }
+        };
+
+        Ok(Comment {
+            text,
+            block,
+            trailing: false,
+        })
+    }
+
+    //start is the opening quote, either `"` or `'`
+    fn parse_string(&mut self, start: Span, quote: char) -> Result<String, ParserError> {
+        let mut key = Decoded::new(!self.skim);
+        //a high surrogate waiting for its low half, and its escape
+        let mut high: Option<(u32, Span)> = None;
+
+        loop {
+            if key.len > self.opts.max_string_length {
+                let span = self.span_from(start);
+                return Err(ParserError::StringTooLong(
+                    self.opts.max_string_length,
+                    span,
+                ));
+            }
+
+            if high.is_none() {
+                self.copy_run(&mut key, quote);
+            }
+
+            let ch = match self.bump()? {
+                Some(ch) => ch,
+                None => return Err(self.eol()),
+            };
+
+            if ch != '\\' {
+                if let Some((code, span)) = high.take() {
+                    self.lone_surrogate(&mut key, code, span)?;
+                }
+            }
+
+            let allowed = match ch {
+                '\n' | '\r' => self.opts.raw_line_breaks,
+                _ => self.opts.control_chars,
+            };
+            if ch < '\x20' && !allowed {
+                self.report(ParserError::ControlCharInString(ch, self.last))?;
+            }
+
+            if ch == quote {
+                break;
+            } else if ch == '\\' {
+                let esc_start = self.last;
+                let esc = match self.bump()? {
+                    Some(esc) => esc,
+                    None => return Err(self.eol()),
+                };
+
+                if esc != 'u' {
+                    if let Some((code, span)) = high.take() {
+                        self.lone_surrogate(&mut key, code, span)?;
+                    }
+                }
+
+                if matches!(esc, '\'' | 'v' | '0' | 'x') && !self.opts.extended_escapes {
+                    let span = self.span_from(esc_start);
+                    self.report(ParserError::EscapeNotAllowed(esc, span))?;
+                }
+
+                if is_line_break(esc) {
+                    if !self.opts.line_continuations {
+                        let span = self.span_from(esc_start);
+                        self.report(ParserError::EscapeNotAllowed(esc, span))?;
+                    }
+
+                    //the line break is left out of the string, \r\n counts as one
+                    if esc == '\r' && self.peek()? == Some('\n') {
+                        self.bump()?;
+                    }
+                    continue;
+                }
+
+                match esc {
+                    '\"' => key.push('\"'),
+                    '\'' => key.push('\''),
+                    '\\' => key.push('\\'),
+                    '/' => key.push('/'),
+                    'n' => key.push('\n'),
+                    't' => key.push('\t'),
+                    'v' => key.push('\x0B'),
+                    'r' => key.push('\r'),
+                    //`\01` would read as octal in some languages, JSON5 leaves it out
+                    '0' if matches!(self.peek()?, Some('0'..='9')) => {
+                        let span = self.span_from(esc_start);
+                        self.report(ParserError::StringEscapeChar(esc, span))?;
+                    }
+                    '0' => key.push('\0'),
+                    'b' => key.push('\x08'),
+                    'f' => key.push('\x0C'),
+                    'x' | 'u' => {
+                        let mut code = 0u32;
+                        let mut valid = true;
+
+                        for _ in 0..(if esc == 'x' { 2 } else { 4 }) {
+                            let x = match self.peek()? {
+                                Some(x) => x,
+                                None => return Err(self.eol()),
+                            };
+
+                            match x.to_digit(16) {
+                                Some(digit) => code = (code << 4) | digit,
+                                None => {
+                                    //left for the string, it may be the closing quote
+                                    let span = self.span_next(x.len_utf8());
+                                    self.report(ParserError::StringUnicode(x, span))?;
+                                    valid = false;
+                                    break;
+                                }
+                            }
+
+                            self.bump()?;
+                        }
+
+                        if !valid {
+                            continue;
+                        }
+
+                        //utf-16 surrogate pairs, \uD83D\uDE00 is one character
+                        let esc_span = self.span_from(esc_start);
+                        if let Some((high_code, high_span)) = high.take() {
+                            if (0xDC00..=0xDFFF).contains(&code) {
+                                let combined =
+                                    0x10000 + ((high_code - 0xD800) << 10) + (code - 0xDC00);
+                                key.push(char::from_u32(combined).unwrap());
+                                continue;
+                            }
+
+                            self.lone_surrogate(&mut key, high_code, high_span)?;
+                        }
+
+                        match code {
+                            0xD800..=0xDBFF => high = Some((code, esc_span)),
+                            0xDC00..=0xDFFF => self.lone_surrogate(&mut key, code, esc_span)?,
+                            _ => key.push(char::from_u32(code).unwrap()),
+                        }
+                    }
+                    _ if self.opts.extended_escapes && !esc.is_ascii_digit() => key.push(esc),
+                    _ => {
+                        let span = self.span_from(esc_start);
+                        self.report(ParserError::StringEscapeChar(esc, span))?;
+                    }
+                }
+            } else {
+                key.push(ch);
+            }
+        }
+
+        Ok(key.text)
+    }
+
+    //skips the ascii whitespace ahead in one go, but for its last character, which bump reads
+    //so last is right
+    fn skip_blanks(&mut self) {
+        let run = self.src.blank_run();
+        if run.len() < 2 || self.pos.offset + run.len() > self.opts.max_document_size {
+            return;
+        }
+
+        let run = &run[..run.len() - 1];
+        let len = run.len();
+        match run.rfind('\n') {
+            Some(nl) => {
+                self.pos.line += run.bytes().filter(|&b| b == b'\n').count();
+                self.pos.pos = len - nl - 1;
+            }
+            None => self.pos.pos += len,
+        }
+
+        self.pos.offset += len;
+        self.src.skip_run(len);
+    }
+
+    //copies the plain characters ahead in one go. anything the limits would catch is left for
+    //the character by character path to report
+    fn copy_run(&mut self, key: &mut Decoded, quote: char) {
+        let run = self.src.plain_run(quote as u8);
+        let last = match run.chars().next_back() {
+            Some(last) => last,
+            None => return,
+        };
+
+        if self.pos.offset + run.len() > self.opts.max_document_size
+            || key.len + run.len() > self.opts.max_string_length
+        {
+            return;
+        }
+
+        key.push_str(run);
+        let len = run.len();
+        let chars = run.chars().count();
+        self.src.skip_run(len);
+
+        self.pos.offset += len;
+        self.pos.pos += chars;
+        self.last = Span {
+            start: self.pos.offset - last.len_utf8(),
+            end: self.pos.offset,
+            line: self.pos.line,
+            pos: self.pos.pos,
+        };
+    }
+
+    //copies the ascii part of a number ahead in one go, the loop after it reads the rest
+    fn copy_word(&mut self) {
+        let run = self.src.word_run();
+        let len = run.len();
+        if len == 0 || self.pos.offset + len > self.opts.max_document_size {
+            return;
+        }
+
+        self.scratch.push_str(run);
+        self.src.skip_run(len);
+
+        self.pos.offset += len;
+        self.pos.pos += len;
+        self.last = Span {
+            start: self.pos.offset - 1,
+            end: self.pos.offset,
+            line: self.pos.line,
+            pos: self.pos.pos,
+        };
+    }
+
+    fn lone_surrogate(
+        &mut self,
+        key: &mut Decoded,
+        code: u32,
+        span: Span,
+    ) -> Result<(), ParserError> {
+        if !self.opts.replace_lone_surrogates {
+            self.report(ParserError::LoneSurrogate(code, span))?;
+        }
+
+        key.push(char::REPLACEMENT_CHARACTER);
+        Ok(())
+    }
+}
+
+//JSON5 identifiers are the ECMAScript ones: a letter, `$` or `_`, then also digits and
+//joiners. char::is_alphabetic stands in for the unicode letter categories
+fn is_ident_start(c: char) -> bool {
+    c.is_alphabetic() || c == '$' || c == '_'
+}
+
+fn is_ident_part(c: char) -> bool {
+    is_ident_start(c) || c.is_alphanumeric() || c == '\u{200C}' || c == '\u{200D}'
+}
+
+pub(crate) fn is_identifier(str: &str) -> bool {
+    let mut chars = str.chars();
+    chars.next().is_some_and(is_ident_start) && chars.all(is_ident_part)
+}
+
+fn is_line_break(c: char) -> bool {
+    matches!(c, '\n' | '\r' | '\u{2028}' | '\u{2029}')
+}
+
+//the JSON5 whitespace besides space, tab and line breaks: unicode White_Space without
+//U+0085, plus the byte order mark
+fn is_unicode_space(c: char) -> bool {
+    (c.is_whitespace() && c != '\u{85}') || c == '\u{FEFF}'
+}
+
+//span is the span of the whole number token
+fn parse_num(str: &str, span: Span, opts: &ParseOptions) -> Result<Number, ParserError> {
+    let mut chars = str.chars();
+    let mut offset = 0;
+
+    let mut beg = match chars.next() {
+        Some(c) => c,
+        None => return Ok(Number::Int(0)), //empty string, technically not possible from the tokens code
+    };
+
+    let negative = beg == '-';
+    if negative || beg == '+' {
+        if !negative && !opts.leading_plus {
+            return Err(ParserError::LeadingPlusNotAllowed(span.sub(0, 1)));
+        }
+
+        offset += 1;
+        match chars.next() {
+            Some(c) => beg = c,
+            None => return Err(ParserError::UnexpectedEOL(span.sub(offset, 0))),
+        }
+    }
+
+    let rest = chars.as_str();
+    if matches!((beg, rest), ('I', "nfinity") | ('N', "aN")) {
+        if !opts.non_finite_numbers {
+            return Err(ParserError::NonFiniteNotAllowed(span));
+        }
+
+        return Ok(Number::Float(match (beg, negative) {
+            ('N', _) => f64::NAN,
+            (_, false) => f64::INFINITY,
+            (_, true) => f64::NEG_INFINITY,
+        }));
+    }
+
+    //0x, 0o, 0b and the 0-prefixed octal
+    let radix_ch = rest.chars().next().unwrap_or('\0');
+    if beg == '0' && matches!(radix_ch, 'x' | 'X' | 'o' | 'O' | 'b' | 'B' | '0'..='9') {
+        let hex = matches!(radix_ch, 'x' | 'X') && opts.hex_integers;
+        if !opts.radix_integers && !hex {
+            return Err(ParserError::RadixNotAllowed(
+                radix_ch,
+                span.sub(offset + 1, radix_ch.len_utf8()),
+            ));
+        }
+
+        let num = parse_radix_int(rest, span, offset + 1)?;
+        return int_from_parts(num, negative, span);
+    }
+
+    if beg == '.' {
+        if !opts.bare_decimal_points {
+            return Err(ParserError::BareDecimalPointNotAllowed(span.sub(offset, 1)));
+        }
+    } else if !rest.contains(['.', 'e', 'E', 'f', 'F', 'd', 'D']) {
+        //decimal integer
+        if !beg.is_ascii_digit() {
+            return Err(ParserError::IntegerInvalidDecimal(
+                beg,
+                span.sub(offset, beg.len_utf8()),
+            ));
+        }
+
+        let mut num = Some((beg as u8 - b'0') as u64);
+        for c in chars {
+            offset += 1;
+
+            if !c.is_ascii_digit() {
+                return Err(ParserError::IntegerInvalidDecimal(
+                    c,
+                    span.sub(offset, c.len_utf8()),
+                ));
+            }
+
+            num = num
+                .and_then(|n| n.checked_mul(10))
+                .and_then(|n| n.checked_add((c as u8 - b'0') as u64));
+        }
+
+        return int_from_parts(num, negative, span);
+    }
+
+    //float
+    let mut decimal = beg == '.';
+    if decimal {
+        //`.5`, a leading decimal point must be followed by a digit
+        match chars.clone().next() {
+            Some(d) if d.is_ascii_digit() => {}
+            Some(d) => {
+                let d_span = span.sub(offset + 1, d.len_utf8());
+                return Err(ParserError::UnexpectedChar(d, d_span));
+            }
+            None => return Err(ParserError::UnexpectedEOL(span.sub(offset + 1, 0))),
+        }
+    } else if !beg.is_ascii_digit() {
+        return Err(ParserError::UnexpectedChar(
+            beg,
+            span.sub(offset, beg.len_utf8()),
+        ));
+    }
+
+    let mut suffix = None;
+
+    while let Some(c) = chars.next() {
+        offset += 1;
+
+        if c.is_ascii_digit() {
+            continue;
+        } else if c == '.' && !decimal {
+            decimal = true;
+
+            //a decimal point must be followed by a digit, unless bare ones like `5.` are allowed
+            match chars.clone().next() {
+                Some(d) if d.is_ascii_digit() => {}
+                None | Some('e' | 'E' | 'f' | 'F' | 'd' | 'D') if opts.bare_decimal_points => {}
+                None | Some('e' | 'E') => {
+                    return Err(ParserError::BareDecimalPointNotAllowed(span.sub(offset, 1)));
+                }
+                Some(d) => {
+                    let d_span = span.sub(offset + 1, d.len_utf8());
+                    return Err(ParserError::UnexpectedChar(d, d_span));
+                }
+            }
+        } else if c == 'e' || c == 'E' {
+            parse_exponent(&mut chars, &mut offset, span)?;
+        } else if matches!(c, 'f' | 'F' | 'd' | 'D') {
+            suffix = Some(c);
+            break;
+        } else {
+            return Err(ParserError::UnexpectedChar(
+                c,
+                span.sub(offset, c.len_utf8()),
+            ));
+        }
+    }
+
+    let mut digits = str;
+    if let Some(c) = suffix {
+        if !opts.float_suffixes {
+            return Err(ParserError::FloatSuffixNotAllowed(c, span.sub(offset, 1)));
+        }
+
+        if let Some(c) = chars.next() {
+            return Err(ParserError::UnexpectedChar(
+                c,
+                span.sub(offset + 1, c.len_utf8()),
+            ));
+        }
+
+        digits = &str[..str.len() - 1];
+    }
+
+    //the text is valid by now, the std conversion rounds correctly to the nearest f64
+    match digits.parse::<f64>() {
+        Ok(num) => Ok(Number::Float(num)),
+        Err(_) => Err(ParserError::UnexpectedChar(beg, span.sub(0, 1))),
+    }
+}
+
+//the exponent after 'e' or 'E', it always ends the number
+fn parse_exponent(chars: &mut Chars, offset: &mut usize, span: Span) -> Result<(), ParserError> {
+    if let Some('+' | '-') = chars.clone().next() {
+        chars.next();
+        *offset += 1;
+    }
+
+    let mut digits = 0;
+    for c in chars {
+        *offset += 1;
+
+        if !c.is_ascii_digit() {
+            return Err(ParserError::UnexpectedChar(
+                c,
+                span.sub(*offset, c.len_utf8()),
+            ));
+        }
+
+        digits += 1;
+    }
+
+    if digits == 0 {
+        return Err(ParserError::UnexpectedEOL(span.sub(*offset + 1, 0)));
+    }
+
+    Ok(())
+}
+
+//str is everything after the leading 0, found at byte `base` of the number's span.
+//gives the magnitude, None if it does not fit u64
+fn parse_radix_int(str: &str, span: Span, base: usize) -> Result<Option<u64>, ParserError> {
+    let mut chars = str.chars();
+
+    let (radix, offset) = match chars.clone().next() {
+        Some('x' | 'X') => (16, 1),
+        Some('o' | 'O') => (8, 1),
+        Some('b' | 'B') => (2, 1),
+        _ => (8, 0), //the 0 prefix alone
+    };
+
+    if offset == 1 {
+        chars.next();
+        if chars.as_str().is_empty() {
+            return Err(ParserError::UnexpectedEOL(span.sub(base + 1, 0)));
+        }
+    }
+
+    let mut num = Some(0u64);
+    for (i, c) in chars.enumerate() {
+        let digit = match c.to_digit(radix) {
+            Some(d) => d as u64,
+            None => {
+                let err_span = span.sub(base + offset + i, c.len_utf8());
+                return Err(match radix {
+                    2 => ParserError::IntegerInvalidBinary(c, err_span),
+                    8 => ParserError::IntegerInvalidOctal(c, err_span),
+                    _ => ParserError::IntegerInvalidHex(c, err_span),
+                });
+            }
+        };
+
+        num = num
+            .and_then(|n| n.checked_mul(radix as u64))
+            .and_then(|n| n.checked_add(digit));
+    }
+
+    Ok(num)
+}
+
+//whether str is a number in any dialect the parser reads
+pub(crate) fn is_number(str: &str) -> bool {
+    let every = ParseOptions {
+        radix_integers: true,
+        float_suffixes: true,
+        ..ParseOptions::json5()
+    };
+    is_number_with(str, &every)
+}
+
+//whether str is a number under opts, is_number_with(str, &ParseOptions::strict()) is the
+//RFC 8259 grammar
+pub(crate) fn is_number_with(str: &str, opts: &ParseOptions) -> bool {
+    let span = Span {
+        start: 0,
+        end: str.len(),
+        line: 1,
+        pos: 1,
+    };
+
+    matches!(
+        parse_num(str, span, opts),
+        Ok(_) | Err(ParserError::IntegerOverflow(..))
+    )
+}
+
+//the value of a number text accepted by is_number, None for floats and integers outside i128
+pub(crate) fn parse_i128(str: &str) -> Option<i128> {
+    let (negative, radix, digits) = integer_parts(str);
+    let num = u128::from_str_radix(digits, radix).ok()?;
+    if negative {
+        if num > i128::MIN.unsigned_abs() {
+            return None;
+        }
+        Some((num as i128).wrapping_neg())
+    } else {
+        i128::try_from(num).ok()
+    }
+}
+
+//the same integer in decimal digits, however many it takes. None for floats
+pub(crate) fn decimal_digits(str: &str) -> Option<String> {
+    const LIMB: u64 = 1_000_000_000;
+
+    let (negative, radix, digits) = integer_parts(str);
+    if digits.is_empty() {
+        return None;
+    }
+
+    //base 10^9 limbs, the least significant first
+    let mut limbs = vec![0u32];
+    for ch in digits.chars() {
+        let mut carry = ch.to_digit(radix)? as u64;
+        for limb in limbs.iter_mut() {
+            let val = *limb as u64 * radix as u64 + carry;
+            *limb = (val % LIMB) as u32;
+            carry = val / LIMB;
+        }
+        if carry > 0 {
+            limbs.push(carry as u32);
+        }
+    }
+
+    let mut text = String::from(if negative { "-" } else { "" });
+    let mut limbs = limbs.iter().rev();
+    text.push_str(&limbs.next().unwrap().to_string());
+    for limb in limbs {
+        text.push_str(&format!("{limb:09}"));
+    }
+    Some(text)
+}
+
+//the sign, radix and digits of an integer text
+fn integer_parts(str: &str) -> (bool, u32, &str) {
+    let (negative, body) = match str.as_bytes().first() {
+        Some(b'-') => (true, &str[1..]),
+        Some(b'+') => (false, &str[1..]),
+        _ => (false, str),
+    };
+
+    let (radix, digits) = match body.as_bytes() {
+        [b'0', b'x' | b'X', ..] => (16, &body[2..]),
+        [b'0', b'o' | b'O', ..] => (8, &body[2..]),
+        [b'0', b'b' | b'B', ..] => (2, &body[2..]),
+        [b'0', b'0'..=b'9', ..] => (8, &body[1..]),
+        _ => (10, body),
+    };
+    (negative, radix, digits)
+}
+
+//the nearest f64 of a number text accepted by is_number
+pub(crate) fn parse_f64(str: &str) -> Option<f64> {
+    if let Some(int) = parse_i128(str) {
+        return Some(int as f64);
+    }
+
+    let digits = str.trim_end_matches(['f', 'F', 'd', 'D']);
+    digits.parse::<f64>().ok()
+}
+
+//the magnitude is None once it overflowed u64. negative numbers have to fit i64,
+//positive ones too big for i64 become unsigned.
+fn int_from_parts(
+    magnitude: Option<u64>,
+    negative: bool,
+    span: Span,
+) -> Result<Number, ParserError> {
+    match magnitude {
+        Some(num) if negative && num <= i64::MIN.unsigned_abs() => {
+            Ok(Number::Int((num as i64).wrapping_neg()))
+        }
+        Some(num) if !negative && num <= i64::MAX as u64 => Ok(Number::Int(num as i64)),
+        Some(num) if !negative => Ok(Number::UInt(num)),
+        _ => Err(ParserError::IntegerOverflow(span)),
+    }
+}
+
+//true, false, null, numbers and strings
+//a true, false, null, number or string token as a value
+enum Scalar {
+    Bool(bool),
+    Null,
+    Number(Number),
+    Text(String), //a number kept as it is written, see ParseOptions::preserve_numbers
+    String(String),
+}
+
+fn read_scalar<S: Source>(tkn: Token, lexer: &mut Lexer<S>) -> Result<Scalar, ParserError> {
+    let opts = &lexer.opts;
+    match tkn.typ {
+        TokenType::True => Ok(Scalar::Bool(true)),
+        TokenType::False => Ok(Scalar::Bool(false)),
+        TokenType::Null => Ok(Scalar::Null),
+        TokenType::Number if opts.preserve_numbers => {
+            //any size is fine, the text is only checked for its syntax
+            match parse_num(&lexer.scratch, tkn.span, opts) {
+                Ok(_) | Err(ParserError::IntegerOverflow(..)) if lexer.skim => {
+                    Ok(Scalar::Text(String::new()))
+                }
+                Ok(_) | Err(ParserError::IntegerOverflow(..)) => {
+                    Ok(Scalar::Text(lexer.scratch.clone()))
+                }
+                Err(e) => Err(e),
+            }
+        }
+        TokenType::Number => Ok(Scalar::Number(parse_num(&lexer.scratch, tkn.span, opts)?)),
+        TokenType::String => Ok(Scalar::String(tkn.value)),
+        //probably a misspelled true, false or null
+        TokenType::Ident => {
+            let span = tkn.span;
+            let word = lexer.take_text(tkn);
+            Err(match word.chars().next() {
+                Some('t') => ParserError::ExpectedWord("true".to_string(), span),
+                Some('f') => ParserError::ExpectedWord("false".to_string(), span),
+                Some('n') => ParserError::ExpectedWord("null".to_string(), span),
+                _ => ParserError::UnexpectedToken(word, span),
+            })
+        }
+        _ => Err(unexpected(tkn, lexer)),
+    }
+}
+
+fn parse_scalar<S: Source>(
+    cxt: &mut JsonContext,
+    tkn: Token,
+    lexer: &mut Lexer<S>,
+) -> Result<JsonValue, ParserError> {
+    Ok(match read_scalar(tkn, lexer)? {
+        Scalar::Bool(bool) => cxt.val_bool(bool),
+        Scalar::Null => cxt.val_null(),
+        Scalar::Number(Number::Int(int)) => cxt.val_int(int),
+        Scalar::Number(Number::UInt(uint)) => cxt.val_uint(uint),
+        Scalar::Number(Number::Float(float)) => cxt.val_float(float),
+        Scalar::Text(text) => cxt.val_number(text),
+        Scalar::String(str) => cxt.val_string(str),
+    })
+}
+
+//an object or array that parse_nested is filling
+struct Frame {
+    id: u64,
+    close: TokenType,          //CBrk for objects, CSBrk for arrays
+    comma: Option<Span>,       //the comma after the last value, until another value starts
+    len: usize,                //members or elements so far
+    orphan: Option<JsonValue>, //a duplicate key's value under FirstWins, read then dropped
+}
+
+//what parse_member and parse_element found
+enum Item {
+    Value,       //a scalar, already stored
+    Open(Frame), //a new object or array, already stored
+    Close,       //the end of the object or array
+}
+
+//fills root and everything nested in it. the objects and arrays being filled are kept on
+//a stack instead of the call stack, so deep input cannot overflow it
+fn parse_nested<S: Source>(
+    cxt: &mut JsonContext,
+    root: Frame,
+    lexer: &mut Lexer<S>,
+) -> Result<(), ParserError> {
+    let mut stack = vec![root];
+    let mut after_value = false; //waiting for a `,` or the end instead of a value
+
+    while !stack.is_empty() {
+        parse_step(cxt, &mut stack, &mut after_value, lexer)?;
+    }
+
+    Ok(())
+}
+
+//one member or element, or the `,` or end after it. the context only changes once all of
+//its tokens are read, PushParser starts a step over when a chunk ends in the middle of one
+fn parse_step<S: Source>(
+    cxt: &mut JsonContext,
+    stack: &mut Vec<Frame>,
+    after_value: &mut bool,
+    lexer: &mut Lexer<S>,
+) -> Result<(), ParserError> {
+    let frame = stack.last().unwrap();
+    if !*after_value {
+        let item = if frame.close == TokenType::CBrk {
+            parse_member(cxt, frame, lexer, stack.len())
+        } else {
+            parse_element(cxt, frame, lexer, stack.len())
+        };
+
+        match item {
+            Ok(Item::Value) => {
+                stack.last_mut().unwrap().len += 1;
+                *after_value = true;
+            }
+            Ok(Item::Open(frame)) => {
+                stack.last_mut().unwrap().len += 1;
+                stack.push(frame);
+            }
+            Ok(Item::Close) => {
+                end_frame(cxt, stack, lexer);
+                *after_value = true;
+            }
+            Err(e) => {
+                lexer.recover(e, stack)?;
+                *after_value = true;
+            }
+        }
+
+        return Ok(());
+    }
+
+    let end_tkn = loop {
+        match lexer.pop() {
+            Ok(tkn) => break tkn,
+            Err(e) => lexer.recover(e, stack)?,
+        }
+    };
+
+    let close = frame.close;
+    match end_tkn.typ {
+        TokenType::Comma => {
+            stack.last_mut().unwrap().comma = Some(end_tkn.span);
+            *after_value = false;
+        }
+        typ if typ == close => {
+            end_frame(cxt, stack, lexer);
+        }
+        TokenType::CBrk | TokenType::CSBrk if stack.iter().any(|f| f.close == end_tkn.typ) => {
+            //the end of an enclosing object or array, give it back and end this one here
+            let close_ch = if close == TokenType::CBrk { '}' } else { ']' };
+            lexer.report(ParserError::ExpectedChar(close_ch, end_tkn.span))?;
+            lexer.unpop(end_tkn);
+            end_frame(cxt, stack, lexer);
+        }
+        _ => {
+            lexer.report(ParserError::ExpectedChar(',', end_tkn.span))?;
+            if !matches!(end_tkn.typ, TokenType::CBrk | TokenType::CSBrk) {
+                lexer.unpop(end_tkn); //a stray closer is dropped instead
+            }
+            stack.last_mut().unwrap().comma = None;
+            *after_value = false;
+        }
+    }
+
+    Ok(())
+}
+
+//hands the comments read since the last call to the values around them: the ones on the line
+//of the previous token trail prev, the others lead next. without either they stay for later
+fn attach_comments<S: Source>(
+    cxt: &mut JsonContext,
+    lexer: &mut Lexer<S>,
+    prev: Option<CommentSlot>,
+    next: Option<CommentSlot>,
+) {
+    let count = lexer.comments.len();
+    attach_first(cxt, lexer, count, prev, next);
+}
+
+//attach_comments for the first count comments only, the rest stay for later
+fn attach_first<S: Source>(
+    cxt: &mut JsonContext,
+    lexer: &mut Lexer<S>,
+    count: usize,
+    prev: Option<CommentSlot>,
+    next: Option<CommentSlot>,
+) {
+    if count == 0 || (prev.is_none() && next.is_none()) {
+        return;
+    }
+
+    for mut comment in lexer.comments.drain(..count) {
+        match (&prev, &next) {
+            (Some(slot), _) if comment.trailing || next.is_none() => {
+                comment.trailing = true;
+                cxt.push_comment(slot, comment);
+            }
+            (_, Some(slot)) => {
+                comment.trailing = false;
+                cxt.push_comment(slot, comment);
+            }
+            _ => {}
+        }
+    }
+}
+
+//the member or element parsed last in the frame
+fn last_slot(cxt: &JsonContext, frame: &Frame) -> Option<CommentSlot> {
+    if frame.len == 0 {
+        return None;
+    }
+
+    if frame.close == TokenType::CBrk {
+        let key = cxt.last_key(frame.id)?;
+        Some(CommentSlot::Member(frame.id, key.clone()))
+    } else {
+        Some(CommentSlot::Element(frame.id, frame.len - 1))
+    }
+}
+
+//pops the innermost object or array, it is complete. the comments before its end trail its
+//last value, or the object or array itself when it is empty
+fn end_frame<S: Source>(cxt: &mut JsonContext, stack: &mut Vec<Frame>, lexer: &mut Lexer<S>) {
+    let frame = stack.pop().unwrap();
+    if !lexer.comments.is_empty() {
+        let prev = last_slot(cxt, &frame).or_else(|| last_slot(cxt, stack.last()?));
+        attach_comments(cxt, lexer, prev, None);
+    }
+
+    if let Some(val) = frame.orphan {
+        cxt.release_value(val);
+    }
+}
+
+//a new object or array for a `{` or `[` inside `depth` others
+fn open_frame<S: Source>(
+    cxt: &mut JsonContext,
+    tkn: &Token,
+    lexer: &Lexer<S>,
+    depth: usize,
+) -> Result<(JsonValue, Frame), ParserError> {
+    if depth >= lexer.opts.max_depth {
+        return Err(ParserError::DepthLimitExceeded(
+            lexer.opts.max_depth,
+            tkn.span,
+        ));
+    }
+
+    let (val, id, close) = if tkn.typ == TokenType::OBrk {
+        let (val, id) = cxt.val_obj();
+        (val, id, TokenType::CBrk)
+    } else {
+        let (val, id) = cxt.val_array();
+        (val, id, TokenType::CSBrk)
+    };
+
+    let frame = Frame {
+        id,
+        close,
+        comma: None,
+        len: 0,
+        orphan: None,
+    };
+    Ok((val, frame))
+}
+
+//parses one `"key": value` of an object
+fn parse_member<S: Source>(
+    cxt: &mut JsonContext,
+    frame: &Frame,
+    lexer: &mut Lexer<S>,
+    depth: usize,
+) -> Result<Item, ParserError> {
+    let tkn = lexer.pop()?;
+    //slots clone their key, only made when there are comments to attach
+    let prev = match lexer.comments.is_empty() {
+        true => None,
+        false => last_slot(cxt, frame),
+    };
+
+    match tkn.typ {
+        _ if is_key(&tkn, lexer)? => {
+            let span = tkn.span;
+            let key = lexer.take_text(tkn);
+            let lead = lexer.comments.len(); //the ones up to the key
+
+            let policy = lexer.opts.duplicate_keys;
+            let exists = cxt.contains(frame.id, &key);
+            if exists && policy == DuplicateKeys::Error {
+                return Err(ParserError::KeyExists(key, span));
+            }
+
+            if frame.len >= lexer.opts.max_object_members {
+                let max = lexer.opts.max_object_members;
+                return Err(ParserError::TooManyMembers(max, span));
+            }
+
+            lexer.expect(TokenType::Colon)?;
+
+            let value_tkn = lexer.pop()?;
+            if lead > 0 {
+                let next = CommentSlot::Member(frame.id, key.clone());
+                attach_first(cxt, lexer, lead, prev, Some(next));
+            }
+            match value_tkn.typ {
+                TokenType::OBrk | TokenType::OSBrk => {
+                    let (val, mut nframe) = open_frame(cxt, &value_tkn, lexer, depth)?;
+                    if exists && policy == DuplicateKeys::FirstWins {
+                        nframe.orphan = Some(val); //it still has to be valid
+                    } else {
+                        let _ = cxt.set_val_with(frame.id, key, val, policy);
+                    }
+                    Ok(Item::Open(nframe))
+                }
+                _ => {
+                    let value = parse_scalar(cxt, value_tkn, lexer)?;
+                    let _ = cxt.set_val_with(frame.id, key, value, policy);
+                    Ok(Item::Value)
+                }
+            }
+        }
+        TokenType::CBrk => {
+            close_frame(frame, lexer)?;
+            Ok(Item::Close)
+        }
+        _ => Err(unexpected(tkn, lexer)),
+    }
+}
+
+//whether tkn is a key: a string or, if they are allowed, an identifier
+fn is_key<S: Source>(tkn: &Token, lexer: &mut Lexer<S>) -> Result<bool, ParserError> {
+    //true, false, null, Infinity and NaN are identifiers too, `{null: 1}` is fine
+    let ident = match tkn.typ {
+        TokenType::String => return Ok(true),
+        TokenType::Ident => true,
+        TokenType::True | TokenType::False | TokenType::Null => true,
+        TokenType::Number => is_identifier(&lexer.scratch),
+        _ => false,
+    };
+
+    if ident && !lexer.opts.identifier_keys {
+        lexer.report(ParserError::IdentifierKeyNotAllowed(tkn.span))?;
+    }
+
+    Ok(ident)
+}
+
+//parses one value of an array
+fn parse_element<S: Source>(
+    cxt: &mut JsonContext,
+    frame: &Frame,
+    lexer: &mut Lexer<S>,
+    depth: usize,
+) -> Result<Item, ParserError> {
+    let tkn = lexer.pop()?;
+    let ends = matches!(
+        tkn.typ,
+        TokenType::Comma | TokenType::CBrk | TokenType::CSBrk
+    );
+
+    let prev = last_slot(cxt, frame);
+    let next = (!ends).then_some(CommentSlot::Element(frame.id, frame.len));
+    attach_comments(cxt, lexer, prev, next);
+
+    if frame.len >= lexer.opts.max_array_length && !ends {
+        let err = ParserError::ArrayTooLong(lexer.opts.max_array_length, tkn.span);
+        lexer.unpop(tkn); //recovering skips the whole value
+        return Err(err);
+    }
+
+    match tkn.typ {
+        TokenType::OBrk | TokenType::OSBrk => {
+            let (val, nframe) = open_frame(cxt, &tkn, lexer, depth)?;
+            cxt.array_push(frame.id, val);
+            Ok(Item::Open(nframe))
+        }
+        TokenType::CSBrk => {
+            close_frame(frame, lexer)?;
+            Ok(Item::Close)
+        }
+        _ => {
+            let value = parse_scalar(cxt, tkn, lexer)?;
+            cxt.array_push(frame.id, value);
+            Ok(Item::Value)
+        }
+    }
+}
+
+//a `}` or `]` where a value could start: right after the `{`/`[`, or after a trailing comma
+fn close_frame<S: Source>(frame: &Frame, lexer: &mut Lexer<S>) -> Result<(), ParserError> {
+    if let Some(comma) = frame.comma {
+        if !lexer.opts.trailing_commas {
+            lexer.report(ParserError::TrailingCommaNotAllowed(comma))?;
+        }
+    }
+
+    Ok(())
+}
+
+//the error for a token that cannot start a value here. a `,`, `}` or `]` is given back,
+//recovering starts from it
+fn unexpected<S: Source>(tkn: Token, lexer: &mut Lexer<S>) -> ParserError {
+    let err = ParserError::UnexpectedToken(lexer.text(&tkn), tkn.span);
+    if matches!(
+        tkn.typ,
+        TokenType::Comma | TokenType::CBrk | TokenType::CSBrk
+    ) {
+        lexer.unpop(tkn);
+    }
+    err
+}
+
+//fills cxt with the document. root_id is set as soon as the root object or array exists,
+//so it is right for whatever was parsed before an error
+fn parse_document<S: Source>(
+    cxt: &mut JsonContext,
+    root_id: &mut u64,
+    lexer: &mut Lexer<S>,
+) -> Result<(), ParserError> {
+    let token = match lexer.next_token()? {
+        Some(token) => token,
+        None => return Err(ParserError::EmptyString(lexer.span_next(0))),
+    };
+    attach_comments(cxt, lexer, None, Some(CommentSlot::Root));
+
+    match token.typ {
+        TokenType::OBrk | TokenType::OSBrk => {
+            let (root, frame) = open_frame(cxt, &token, lexer, 0)?;
+            cxt.set_root(root);
+            *root_id = frame.id;
+            parse_nested(cxt, frame, lexer)?;
+        }
+        TokenType::True
+        | TokenType::False
+        | TokenType::Null
+        | TokenType::Number
+        | TokenType::String
+        | TokenType::Ident => {
+            let root = parse_scalar(cxt, token, lexer)?;
+            cxt.set_root(root); //scalars have no id, root_id stays 0
+        }
+        _ => {
+            return Err(ParserError::BadBeginning(lexer.text(&token), token.span));
+        }
+    }
+
+    if let Some(tkn_end) = lexer.next_token()? {
+        return Err(ParserError::UnexpectedToken(
+            lexer.text(&tkn_end),
+            tkn_end.span,
+        ));
+    }
+    attach_comments(cxt, lexer, Some(CommentSlot::Root), None);
+
+    Ok(())
+}
+
+fn parse_all<S: Source>(lexer: &mut Lexer<S>) -> Result<(JsonContext, u64), ParserError> {
+    let mut cxt = JsonContext::new_empty();
+    let mut root_id = 0;
+    parse_document(&mut cxt, &mut root_id, lexer)?;
+    Ok((cxt, root_id))
+}
+
+pub fn parse(str: &str) -> Result<(JsonContext, u64), ParserError> {
+    parse_with(str, ParseOptions::relaxed())
+}
+
+pub fn parse_with(str: &str, opts: ParseOptions) -> Result<(JsonContext, u64), ParserError> {
+    let src = StrSource::new(str);
+    parse_all(&mut Lexer::new(src, opts))
+}
+
+//every token of a document that parses with opts, for cst::CstDocument
+pub(crate) fn tokenize(str: &str, opts: ParseOptions) -> Result<Vec<Token>, ParserError> {
+    parse_with(str, opts)?;
+
+    let mut lexer = Lexer::new(StrSource::new(str), opts);
+    let mut tokens = Vec::new();
+    while let Some(mut tkn) = lexer.next_token()? {
+        if tkn.typ == TokenType::Number {
+            tkn.value = lexer.scratch.clone();
+        }
+        tokens.push(tkn);
+    }
+
+    Ok(tokens)
+}
+
+/**
+* parses as much of a document as possible instead of stopping at the first error.
+* after a bad value the parser skips to the next `,`, `}` or `]` and carries on, so the
+* context holds every well-formed part. the errors are in the order they were found,
+* the document is valid if there are none.
+*/
+pub fn parse_recovering(str: &str, opts: ParseOptions) -> (JsonContext, u64, Vec<ParserError>) {
+    let src = StrSource::new(str);
+    let mut lexer = Lexer::new(src, opts);
+    lexer.errors = Some(Vec::new());
+
+    let mut cxt = JsonContext::new_empty();
+    let mut root_id = 0;
+    let result = parse_document(&mut cxt, &mut root_id, &mut lexer);
+
+    let mut errors = lexer.errors.take().unwrap_or_default();
+    if let Err(e) = result {
+        errors.push(e);
+    }
+
+    (cxt, root_id, errors)
+}
+
+/**
+* parses a document while reading it, only a small buffer of the input is kept in memory.
+* std::io::BufReader is not needed, the reader is already read in large blocks.
+*/
+pub fn parse_reader<R: Read>(
+    reader: R,
+    opts: ParseOptions,
+) -> Result<(JsonContext, u64), ParserError> {
+    parse_all(&mut Lexer::new(ReadSource::new(reader), opts))
+}
+
+pub fn parse_bytes(bytes: &[u8]) -> Result<(JsonContext, u64), ParserError> {
+    parse_bytes_with(bytes, ParseOptions::relaxed())
+}
+
+/**
+* parses bytes in utf-8, utf-16 or utf-32, see detect_encoding. a byte order mark is skipped.
+* an invalid sequence is reported at its byte offset in bytes. other errors are at their
+* offset in bytes too for utf-8, and in the text turned into utf-8 for the others.
+*/
+pub fn parse_bytes_with(
+    bytes: &[u8],
+    opts: ParseOptions,
+) -> Result<(JsonContext, u64), ParserError> {
+    let (enc, bom) = detect_encoding(bytes);
+    let body = &bytes[bom..];
+
+    let text = match enc {
+        Encoding::Utf8 => match std::str::from_utf8(body) {
+            Ok(text) => text,
+            Err(e) => {
+                let valid = e.valid_up_to();
+                let len = e.error_len().unwrap_or(body.len() - valid);
+                let prefix = std::str::from_utf8(&body[..valid]).unwrap();
+                return Err(ParserError::InvalidUtf8(span_after(
+                    prefix,
+                    bom + valid,
+                    len,
+                )));
+            }
+        },
+        _ => {
+            let text = transcode(body, enc, bom)?;
+            return parse_with(&text, opts);
+        }
+    };
+
+    //the offsets stay those of bytes
+    let mut lexer = Lexer::new(StrSource::new(text), opts);
+    lexer.pos.offset = bom;
+    parse_all(&mut lexer)
+}
+
+//the span of len bytes at offset, right after the text prefix
+fn span_after(prefix: &str, offset: usize, len: usize) -> Span {
+    let line_start = prefix.rfind('\n').map_or(0, |nl| nl + 1);
+    Span {
+        start: offset,
+        end: offset + len,
+        line: prefix.matches('\n').count() + 1,
+        pos: prefix[line_start..].chars().count() + 1,
+    }
+}
+
+//utf-16 or utf-32 to a string, bom is the length of the byte order mark before body
+fn transcode(body: &[u8], enc: Encoding, bom: usize) -> Result<String, ParserError> {
+    let width = match enc {
+        Encoding::Utf16Le | Encoding::Utf16Be => 2,
+        _ => 4,
+    };
+
+    let units = body
+        .chunks(width)
+        .map(|unit| match (enc, unit.len() == width) {
+            (_, false) => None, //the last unit is cut short
+            (Encoding::Utf16Le, _) => Some(u16::from_le_bytes([unit[0], unit[1]]) as u32),
+            (Encoding::Utf16Be, _) => Some(u16::from_be_bytes([unit[0], unit[1]]) as u32),
+            (Encoding::Utf32Le, _) => Some(u32::from_le_bytes(unit.try_into().unwrap())),
+            _ => Some(u32::from_be_bytes(unit.try_into().unwrap())),
+        });
+
+    let mut text = String::new();
+    let mut offset = bom;
+    let mut high: Option<u32> = None; //a utf-16 high surrogate waiting for its low half
+
+    for unit in units {
+        let c = match (unit, high.take()) {
+            (Some(low @ 0xDC00..=0xDFFF), Some(hi)) if width == 2 => {
+                char::from_u32(0x10000 + ((hi - 0xD800) << 10) + (low - 0xDC00))
+            }
+            (_, Some(_)) => {
+                offset -= width; //the high surrogate has no low half
+                None
+            }
+            (Some(hi @ 0xD800..=0xDBFF), None) if width == 2 => {
+                high = Some(hi);
+                offset += width;
+                continue;
+            }
+            (Some(unit), None) => char::from_u32(unit),
+            (None, None) => None,
+        };
+
+        match c {
+            Some(c) => text.push(c),
+            None => {
+                let len = (bom + body.len() - offset).min(width);
+                let span = span_after(&text, offset, len);
+                return Err(ParserError::InvalidEncoding(enc, span));
+            }
+        }
+        offset += width;
+    }
+
+    if high.is_some() {
+        let span = span_after(&text, offset - width, width);
+        return Err(ParserError::InvalidEncoding(enc, span));
+    }
+
+    Ok(text)
+}
+
+/**
+* parses a document that arrives in chunks. feed takes the chunks as they come, a chunk may
+* end in the middle of a token or of a utf-8 character. everything up to the last complete
+* member or element is parsed right away and its bytes are let go. finish says there is no
+* more input and gives the context. errors have the same positions parse_with would give,
+* after one every feed and finish returns it again.
+*/
+pub struct PushParser {
+    lexer: Lexer<PushSource>,
+    cxt: JsonContext,
+    root_id: u64,
+    stack: Vec<Frame>,
+    after_value: bool,
+    state: PushState,
+    awaits: Option<Awaits>,
+    failed: Option<ParserError>,
+    rescanned: usize, //the bytes read by steps that were undone
 }
 
-#[derive(Debug)]
-struct Token {
-    value: String,
-    typ: TokenType,
-    line: usize,
-    pos: usize,
+//what the token a chunk ended in needs before it can end. chunks without it are only stored,
+//a long string is not read again from its start for every chunk
+#[derive(Clone, Copy)]
+enum Awaits {
+    Byte(u8), //the closing quote, the `\n` of a line comment or the `/` of a `*/`
+    WordEnd,  //numbers and identifiers end at any ascii character they cannot hold
 }
 
-impl Token {
-    fn new(value: String, typ: TokenType, line: usize, pos: usize) -> Self {
-        Self {
-            value,
-            typ,
-            line,
-            pos,
+impl Awaits {
+    //for the token that starts the bytes
+    fn of(token: &[u8]) -> Option<Self> {
+        match token {
+            [quote @ (b'"' | b'\''), ..] => Some(Awaits::Byte(*quote)),
+            [b'/', b'/', ..] => Some(Awaits::Byte(b'\n')),
+            [b'/', b'*', ..] => Some(Awaits::Byte(b'/')),
+            [b'/'] | [] => None,
+            _ => Some(Awaits::WordEnd),
         }
     }
 
-    fn new_str(value: &str, typ: TokenType, line: usize, pos: usize) -> Self {
-        Self::new(value.to_string(), typ, line, pos)
+    fn found(self, bytes: &[u8]) -> bool {
+        match self {
+            Awaits::Byte(byte) => bytes.contains(&byte),
+            Awaits::WordEnd => bytes
+                .iter()
+                .any(|&b| b.is_ascii() && !(b.is_ascii_alphanumeric() || b"._+-$\\".contains(&b))),
+        }
     }
+}
 
-    fn new_ch(value: char, typ: TokenType, line: usize, pos: usize) -> Self {
-        Self::new(value.to_string().to_string(), typ, line, pos)
-    }
+enum PushState {
+    Start,  //nothing read yet
+    Nested, //inside the root object or array
+    End,    //after the root, only whitespace and comments may follow
 }
 
-fn eat_whitespace(chars: &mut Chars, pos: &mut Position) -> (bool, char) {
-    'main_loop: loop {
-        let c = chars.next();
+impl PushParser {
+    pub fn new(opts: ParseOptions) -> Self {
+        let src = PushSource {
+            buf: Vec::new(),
+            start: 0,
+            finished: false,
+            starved: false,
+        };
 
-        match c {
-            Some(x) => {
-                if x == '\n' {
-                    pos.line += 1;
-                    pos.pos = 0;
-                    continue 'main_loop;
-                } else {
-                    pos.pos += 1;
-                }
+        Self {
+            lexer: Lexer::new(src, opts),
+            cxt: JsonContext::new_empty(),
+            root_id: 0,
+            stack: Vec::new(),
+            after_value: false,
+            state: PushState::Start,
+            awaits: None,
+            failed: None,
+            rescanned: 0,
+        }
+    }
 
-                if x != ' ' && x != '\t' {
-                    return (false, x);
-                }
-            }
-            None => {
-                return (true, '\0');
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<(), ParserError> {
+        if let Some(e) = &self.failed {
+            return Err(e.clone());
+        }
+
+        let src = &mut self.lexer.src;
+        let new = src.buf.len();
+        src.buf.extend_from_slice(chunk);
+        if let Some(awaits) = self.awaits {
+            if !awaits.found(&src.buf[new..]) {
+                return Ok(());
             }
         }
+
+        self.run()
     }
-}
 
-fn parse_num(str: &String, line: usize, pos: usize) -> Result<(i64, f64, bool), ParserError> {
-    let mut chars = str.chars();
-    let beg_op = chars.next();
-
-    match beg_op {
-        Some(mut beg) => {
-            let mut offset = 0;
-            let negative = beg == '-';
-            if negative || beg == '+' {
-                match chars.next() {
-                    Some(c) => {
-                        beg = c;
-                    }
-                    None => {
-                        return Err(ParserError::UnexpectedEOL(line, pos + 1));
+    pub fn finish(mut self) -> Result<(JsonContext, u64), ParserError> {
+        if let Some(e) = self.failed {
+            return Err(e);
+        }
+
+        self.lexer.src.finished = true;
+        self.run()?;
+        Ok((self.cxt, self.root_id))
+    }
+
+    //the bytes that were read by a step that ran out of input, they are read again when it
+    //starts over. a chunked document is read about once if this stays small
+    pub fn rescanned(&self) -> usize {
+        self.rescanned
+    }
+
+    //steps until the input runs out. a step that runs out is undone, it starts over when
+    //a chunk brings what its last token awaits
+    fn run(&mut self) -> Result<(), ParserError> {
+        self.awaits = None;
+        loop {
+            let pos = self.lexer.pos;
+            let last = self.lexer.last;
+            let token_line = self.lexer.token_line;
+            let comments = self.lexer.comments.len();
+            let start = self.lexer.src.start;
+            self.lexer.src.starved = false;
+
+            match self.step() {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(_) if self.lexer.src.starved => {
+                    if let Some(at) = self.lexer.token_start.take() {
+                        let token = start + at - pos.offset;
+                        self.awaits = Awaits::of(&self.lexer.src.buf[token..]);
                     }
+                    self.rescanned += self.lexer.src.buf.len() - start;
+
+                    self.lexer.pos = pos;
+                    self.lexer.last = last;
+                    self.lexer.token_line = token_line;
+                    self.lexer.comments.truncate(comments);
+                    self.lexer.src.start = start;
+                    self.lexer.pending = None;
+                    break;
+                }
+                Err(e) => {
+                    self.failed = Some(e.clone());
+                    return Err(e);
                 }
             }
-            
-            if str.contains('.')
-                || str.contains('f')
-                || str.contains('F')
-                || str.contains('d')
-                || str.contains('D')
-                || str.contains('e')
-                || str.contains('E')
-            {
-                //float
-                let mut num = 0f64;
-                let mut exp = 0.1f64;
-                let mut decimal = false;
-
-                let beg_num = beg as u8;
-                if beg_num >= b'0' && beg_num <= b'9' {
-                    num = (beg_num - b'0') as f64;
-                } else if beg == '.' {
-                    decimal = true;
-                } else {
-                    return Err(ParserError::UnexpectedChar(beg, line, pos + offset));
-                }
-
-                while let Some(c) = chars.next() {
-                    offset += 1;
-
-                    if c == '.' {
-                        if decimal {
-                            return Err(ParserError::UnexpectedChar(c, line, pos + offset));
-                        }
-                        decimal = true;
-                        continue;
-                    }
-
-                    let c_num = c as u8;
-                    if c_num >= b'0' && c_num <= b'9' {
-                        if decimal {
-                            num += (c_num - b'0') as f64 * exp;
-                            exp *= 0.1;
-                        } else {
-                            num *= 10.0;
-                            num += (c_num - b'0') as f64;
-                        }
-                    } else if c == 'f' || c == 'F' || c == 'd' || c == 'D' {
-                        break;
-                    } else if c == 'e' || c == 'E' {
-                        offset += 1;
-                        match chars.next() {
-                            Some(beg_e) => {
-                                let mut exp = 0isize;
-                                let mut exp_neg = false;
-                                if beg_e == '+' || beg_e == '-' {
-                                    if beg_e == '-' {
-                                        exp_neg = true;
-                                    }
-                                } else if beg_e.is_numeric() {
-                                    exp = (beg_e as u8 - b'0') as isize;
-                                } else {
-                                    return Err(ParserError::UnexpectedChar(
-                                        beg_e,
-                                        line,
-                                        pos + offset,
-                                    ));
-                                }
-
-                                while let Some(char_e) = chars.next() {
-                                    offset += 1;
-                                    let char_e_num = char_e as u8;
-                                    if char_e_num >= b'0' && char_e_num <= b'9' {
-                                        exp = exp * 10 + (char_e_num - b'0') as isize;
-                                    } else {
-                                        return Err(ParserError::UnexpectedChar(
-                                            char_e,
-                                            line,
-                                            pos + offset,
-                                        ));
-                                    }
-                                }
+        }
 
-                                if exp_neg {
-                                    for _ in 0..exp {
-                                        num *= 0.1;
-                                    }
-                                } else {
-                                    for _ in 0..exp {
-                                        num *= 10.0;
-                                    }
-                                }
+        //the bytes that were parsed are not needed anymore
+        let src = &mut self.lexer.src;
+        src.buf.drain(..src.start);
+        src.start = 0;
+        Ok(())
+    }
 
-                                break;
-                            }
-                            None => {
-                                return Err(ParserError::UnexpectedEOL(line, pos + offset));
-                            }
-                        }
-                    } else {
-                        return Err(ParserError::UnexpectedChar(c, line, pos + offset));
+    //false when the document is complete
+    fn step(&mut self) -> Result<bool, ParserError> {
+        let lexer = &mut self.lexer;
+        let cxt = &mut self.cxt;
+
+        match self.state {
+            PushState::Start => {
+                let token = match lexer.next_token()? {
+                    Some(token) => token,
+                    None => return Err(ParserError::EmptyString(lexer.span_next(0))),
+                };
+                attach_comments(cxt, lexer, None, Some(CommentSlot::Root));
+
+                match token.typ {
+                    TokenType::OBrk | TokenType::OSBrk => {
+                        let (root, frame) = open_frame(cxt, &token, lexer, 0)?;
+                        cxt.set_root(root);
+                        self.root_id = frame.id;
+                        self.stack.push(frame);
+                        self.state = PushState::Nested;
                     }
+                    TokenType::True
+                    | TokenType::False
+                    | TokenType::Null
+                    | TokenType::Number
+                    | TokenType::String
+                    | TokenType::Ident => {
+                        let root = parse_scalar(cxt, token, lexer)?;
+                        cxt.set_root(root);
+                        self.state = PushState::End;
+                    }
+                    _ => return Err(ParserError::BadBeginning(lexer.text(&token), token.span)),
                 }
-
-                let next_char = chars.next();
-                if next_char.is_some() {
-                    return Err(ParserError::UnexpectedChar(
-                        next_char.unwrap(),
-                        line,
-                        pos + offset + 1,
+            }
+            PushState::Nested => {
+                parse_step(cxt, &mut self.stack, &mut self.after_value, lexer)?;
+                if self.stack.is_empty() {
+                    self.state = PushState::End;
+                }
+            }
+            PushState::End => {
+                if let Some(tkn_end) = lexer.next_token()? {
+                    return Err(ParserError::UnexpectedToken(
+                        lexer.text(&tkn_end),
+                        tkn_end.span,
                     ));
                 }
+                attach_comments(cxt, lexer, Some(CommentSlot::Root), None);
+                return Ok(false);
+            }
+        }
 
-                if negative {
-                    num = -num;
-                }
-                
-                return Ok((0, num, true));
-            } else {
-                //number
-                let mut num = 0u64;
-                let mut radix = 10;
+        Ok(true)
+    }
+}
 
-                if beg == '0' {
-                    let typ_op = chars.next();
+//what a JsonVisitor wants after each callback
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visit {
+    Continue,
+    Stop,
+}
 
-                    if typ_op == None {
-                        return Ok((0, 0.0, false));
-                    }
+/**
+* the callbacks of parse_events, in the order of the document. every one of them does nothing
+* by default. numbers come to int, uint (above i64::MAX) or float, or to number with their
+* text when ParseOptions::preserve_numbers is on. returning Visit::Stop ends the parsing.
+*/
+pub trait JsonVisitor {
+    fn start_object(&mut self) -> Visit {
+        Visit::Continue
+    }
 
-                    let typ = typ_op.unwrap();
+    fn key(&mut self, _key: &str) -> Visit {
+        Visit::Continue
+    }
 
-                    match typ {
-                        'x' | 'X' => radix = 16,
-                        'b' | 'B' => radix = 2,
-                        _ => {
-                            radix = 8;
-
-                            let typ_b = typ as u8;
-                            if typ_b >= b'0' && typ_b <= b'7' {
-                                num = (typ_b - b'0') as u64;
-                            } else if typ != 'o' && typ != 'O' {
-                                return Err(ParserError::IntegerInvalidOctal(
-                                    typ,
-                                    line,
-                                    pos + offset + 1,
-                                ));
-                            }
-                        }
-                    }
+    fn end_object(&mut self) -> Visit {
+        Visit::Continue
+    }
 
-                    offset += 2;
-                } else {
-                    let beg_b = beg as u8;
-                    if beg_b >= b'0' && beg_b <= b'9' {
-                        num = (beg_b - b'0') as u64;
-                    } else {
-                        return Err(ParserError::IntegerInvalidOctal(beg, line, pos + offset));
-                    }
-                    offset += 1;
-                }
+    fn start_array(&mut self) -> Visit {
+        Visit::Continue
+    }
 
-                while let Some(c) = chars.next() {
-                    let c_num = c as u8;
+    fn end_array(&mut self) -> Visit {
+        Visit::Continue
+    }
 
-                    offset += 1;
+    fn string(&mut self, _val: &str) -> Visit {
+        Visit::Continue
+    }
 
-                    match radix {
-                        2 => {
-                            if c != '0' && c != '1' {
-                                return Err(ParserError::IntegerInvalidBinary(
-                                    c,
-                                    line,
-                                    pos + offset,
-                                ));
-                            }
-                            num <<= 1;
-                        }
-                        8 => {
-                            if c_num < b'0' || c_num > b'7' {
-                                return Err(ParserError::IntegerInvalidOctal(
-                                    c,
-                                    line,
-                                    pos + offset,
-                                ));
-                            }
-                            num <<= 3;
-                        }
-                        10 => {
-                            if c_num < b'0' || c_num > b'9' {
-                                return Err(ParserError::IntegerInvalidDecimal(
-                                    c,
-                                    line,
-                                    pos + offset,
-                                ));
-                            }
-                            num *= 10;
-                        }
-                        16 => {
-                            if !(c_num >= b'0' || c_num <= b'9')
-                                && !(c_num >= b'a' || c_num <= b'f')
-                                && !(c_num >= b'A' || c_num <= b'F')
-                            {
-                                return Err(ParserError::IntegerInvalidHex(c, line, pos + offset));
-                            }
-                            num <<= 4;
-                        }
-                        _ => { /* should not happen */ }
-                    }
+    fn int(&mut self, _val: i64) -> Visit {
+        Visit::Continue
+    }
 
-                    if c_num >= b'0' && c_num <= b'9' {
-                        num += (c_num - b'0') as u64;
-                    } else if c_num >= 'a' as u8 && c_num <= 'f' as u8 {
-                        num += (c_num - b'a' + 10) as u64;
-                    } else if c_num >= 'A' as u8 && c_num <= 'F' as u8 {
-                        num += (c_num - b'A' + 10) as u64;
-                    } else {
-                        break;
-                    }
-                }
+    fn uint(&mut self, _val: u64) -> Visit {
+        Visit::Continue
+    }
 
-                return Ok((
-                    num as i64 * if negative { -1 } else { 1 },
-                    num as f64,
-                    false,
-                ));
-            }
-        }
-        None => {
-            Ok((0, 0.0, false)) //empty string, technically not possible from the tokens code
-        }
+    fn float(&mut self, _val: f64) -> Visit {
+        Visit::Continue
     }
-}
 
-fn parse_string(chars: &mut Chars, pos: &mut Position) -> Result<String, ParserError> {
-    let mut key = String::new();
+    fn number(&mut self, _text: &str) -> Visit {
+        Visit::Continue
+    }
 
-    loop {
-        pos.pos += 1;
-        let ch_op = chars.next();
+    fn bool(&mut self, _val: bool) -> Visit {
+        Visit::Continue
+    }
 
-        match ch_op {
-            Some(ch) => {
-                //TODO escape character handling
-                if ch == '\"' {
-                    break;
-                } else if ch == '\\' {
-                    pos.pos += 1;
-                    match chars.next() {
-                        Some(esc) => match esc {
-                            '\"' => key.push('\"'),
-                            '\'' => key.push('\''),
-                            '\\' => key.push('\\'),
-                            'n' => key.push('\n'),
-                            't' => key.push('\t'),
-                            'v' => key.push('\x0B'),
-                            'r' => key.push('\r'),
-                            '0' => key.push('\0'),
-                            'b' => key.push('\x08'),
-                            'f' => key.push('\x0C'),
-                            'x' | 'u' => {
-                                let mut code = 0u32;
-
-                                for _ in 0..(if esc == 'x' { 2 } else { 4 }) {
-                                    pos.pos += 1;
-                                    let ch_esc = chars.next();
-
-                                    match ch_esc {
-                                        Some(x) => {
-                                            code <<= 4;
-
-                                            if x >= '0' && x <= '9' {
-                                                code |= ((x as u8) - b'0') as u32;
-                                            } else if x >= 'a' && x <= 'f' {
-                                                code |= ((x as u8) - b'a' + 10) as u32;
-                                            } else if x >= 'A' && x <= 'F' {
-                                                code |= ((x as u8) - b'A' + 10) as u32;
-                                            } else {
-                                                return Err(ParserError::StringUnicode(
-                                                    x, pos.line, pos.pos,
-                                                ));
-                                            }
-                                        }
-                                        None => {
-                                            return Err(ParserError::UnexpectedEOL(
-                                                pos.line, pos.pos,
-                                            ));
-                                        }
-                                    }
-                                }
+    fn null(&mut self) -> Visit {
+        Visit::Continue
+    }
+}
 
-                                key.push(char::from_u32(code).unwrap());
-                            }
-                            _ => {
-                                return Err(ParserError::StringEscapeChar(esc, pos.line, pos.pos));
-                            }
-                        },
-                        None => {
-                            return Err(ParserError::UnexpectedEOL(pos.line, pos.pos));
-                        }
-                    }
-                } else {
-                    key.push(ch);
-                }
-            }
-            None => {
-                break;
-            }
+/**
+* parses str without building a JsonContext, visitor is told about every value instead.
+* the document is checked just like parse_with checks it. Ok(false) when the visitor stopped
+* before the end, the rest of str is not looked at then.
+*/
+pub fn parse_events<V: JsonVisitor>(
+    str: &str,
+    opts: ParseOptions,
+    visitor: &mut V,
+) -> Result<bool, ParserError> {
+    let mut pull = PullParser::new(str, opts);
+
+    while let Some((event, _)) = pull.next_event()? {
+        let visit = match event {
+            Event::StartObject => visitor.start_object(),
+            Event::EndObject => visitor.end_object(),
+            Event::StartArray => visitor.start_array(),
+            Event::EndArray => visitor.end_array(),
+            Event::Key(key) => visitor.key(&key),
+            Event::String(str) => visitor.string(&str),
+            Event::Int(int) => visitor.int(int),
+            Event::UInt(uint) => visitor.uint(uint),
+            Event::Float(float) => visitor.float(float),
+            Event::Number(text) => visitor.number(&text),
+            Event::Bool(bool) => visitor.bool(bool),
+            Event::Null => visitor.null(),
+        };
+
+        if visit == Visit::Stop {
+            return Ok(false);
         }
     }
 
-    return Ok(key);
+    Ok(true)
 }
 
-fn pop_token(tokens: &mut Vec<Token>, expect: TokenType) -> Result<Token, ParserError> {
-    match tokens.pop() {
-        Some(token) => {
-            if token.typ != expect {
-                return Err(ParserError::UnexpectedToken(
-                    token.value,
-                    token.line,
-                    token.pos,
-                ));
-            }
+//what PullParser reads, one at a time
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    StartObject,
+    EndObject,
+    StartArray,
+    EndArray,
+    Key(String),
+    String(String),
+    Int(i64),
+    UInt(u64), //above i64::MAX
+    Float(f64),
+    Number(String), //with ParseOptions::preserve_numbers
+    Bool(bool),
+    Null,
+}
 
-            Ok(token)
-        }
-        None => Err(ParserError::UnexpectedEndOfTokens()),
-    }
+/**
+* reads a document one event at a time, every event with the span of its token. the document
+* is checked as it goes just like parse_with checks it, an error ends it. comments are skipped.
+*/
+pub struct PullParser<'a> {
+    text: &'a str,
+    lexer: Lexer<StrSource<'a>>,
+    stack: Vec<PullFrame<'a>>,
+    state: PullState,
+    peeked: Option<(Event, Span)>,
+}
+
+enum PullState {
+    Start,
+    Item,  //a member, element or the end of the object or array
+    Value, //the value of the key just read
+    After, //a `,` or the end after a value
+    End,   //after the root
+    Done,
 }
 
-fn parse_obj(cxt: &mut JsonContext, obj_id: u64, tokens: &mut Vec<Token>) -> Option<ParserError> {
-    let mut err: Option<ParserError> = None;
+//a key the lexer skimmed, as it is in text unless it has escapes. it was checked while it was
+//read, only the escapes are decoded again
+fn skimmed_key<'a>(text: &'a str, tkn: &Token, opts: ParseOptions) -> Cow<'a, str> {
+    let raw = &text[tkn.span.start..tkn.span.end];
+    if raw.contains('\\') {
+        let mut lexer = Lexer::new(StrSource::new(raw), opts);
+        lexer.keep_comments = false;
+        return match lexer.next_token() {
+            Ok(Some(key)) => Cow::Owned(lexer.take_text(key)),
+            _ => Cow::Borrowed(raw),
+        };
+    }
 
-    'obj: loop {
-        match tokens.pop() {
-            Some(tkn) => match tkn.typ {
-                TokenType::String => {
-                    let key = tkn.value;
+    match tkn.typ {
+        TokenType::String => Cow::Borrowed(&raw[1..raw.len() - 1]),
+        _ => Cow::Borrowed(raw),
+    }
+}
 
-                    if cxt.contains(obj_id, &key) {
-                        err.replace(ParserError::KeyExists(key, tkn.line, tkn.pos));
-                        break 'obj;
-                    }
+//an object or array the parser is in
+struct PullFrame<'a> {
+    close: TokenType,
+    comma: Option<Span>,
+    len: usize,
+    keys: Option<HashSet<Cow<'a, str>>>, //for DuplicateKeys::Error
+}
 
-                    match pop_token(tokens, TokenType::Colon) {
-                        Ok(_) => match tokens.pop() {
-                            Some(value_tkn) => {
-                                match value_tkn.typ {
-                                    TokenType::True => {
-                                        cxt.set_val(obj_id, key, cxt.val_bool(true));
-                                    }
-                                    TokenType::False => {
-                                        cxt.set_val(obj_id, key, cxt.val_bool(false));
-                                    }
-                                    TokenType::Null => {
-                                        cxt.set_val(obj_id, key, cxt.val_null());
-                                    }
-                                    TokenType::Number => match parse_num(
-                                        &value_tkn.value,
-                                        value_tkn.line,
-                                        value_tkn.pos,
-                                    ) {
-                                        Ok(int) => {
-                                            if int.2 {
-                                                cxt.set_val(obj_id, key, cxt.val_float(int.1));
-                                            } else {
-                                                cxt.set_val(obj_id, key, cxt.val_int(int.0));
-                                            }
-                                        }
-                                        Err(e) => {
-                                            err.replace(e);
-                                            break 'obj;
-                                        }
-                                    },
-                                    TokenType::String => {
-                                        let value = cxt.val_string(value_tkn.value);
-                                        cxt.set_val(obj_id, key, value);
-                                    }
-                                    TokenType::OBrk => {
-                                        let (nobj, nobj_id) = cxt.val_obj();
-                                        cxt.set_val(obj_id, key, nobj);
-                                        match parse_obj(cxt, nobj_id, tokens) {
-                                            Some(e) => {
-                                                err.replace(e);
-                                                break 'obj;
-                                            }
-                                            None => {}
-                                        }
-                                    }
-                                    TokenType::OSBrk => {
-                                        let (arr, arr_id) = cxt.val_array();
-                                        cxt.set_val(obj_id, key, arr);
-                                        match parse_arr(cxt, arr_id, tokens) {
-                                            Some(e) => {
-                                                err.replace(e);
-                                                break 'obj;
-                                            }
-                                            None => {}
-                                        }
-                                    }
-                                    _ => {
-                                        err.replace(ParserError::UnexpectedToken(
-                                            value_tkn.value,
-                                            value_tkn.line,
-                                            value_tkn.pos,
-                                        ));
-                                        break 'obj;
-                                    }
-                                }
+impl<'a> PullParser<'a> {
+    pub fn new(str: &'a str, opts: ParseOptions) -> Self {
+        let mut lexer = Lexer::new(StrSource::new(str), opts);
+        lexer.keep_comments = false;
 
-                                match tokens.pop() {
-                                    Some(end_tkn) => {
-                                        if end_tkn.typ != TokenType::Comma
-                                            && end_tkn.typ != TokenType::CBrk
-                                        {
-                                            err.replace(ParserError::ExpectedChar(
-                                                ',',
-                                                end_tkn.line,
-                                                end_tkn.pos,
-                                            ));
-                                            break 'obj;
-                                        } else if end_tkn.typ == TokenType::CBrk {
-                                            break 'obj;
-                                        }
-                                    }
-                                    None => {
-                                        err.replace(ParserError::UnexpectedEndOfTokens());
-                                        break 'obj;
-                                    }
-                                }
-                            }
-                            None => {
-                                err.replace(ParserError::UnexpectedEndOfTokens());
-                                break 'obj;
-                            }
-                        },
-                        Err(e) => {
-                            err.replace(e);
-                            break 'obj;
-                        }
-                    }
-                }
-                TokenType::CBrk => {
-                    break 'obj;
-                }
-                _ => {
-                    err.replace(ParserError::UnexpectedToken(tkn.value, tkn.line, tkn.pos));
-                    break 'obj;
-                }
-            },
-            None => {
-                err.replace(ParserError::UnexpectedEndOfTokens());
-                break 'obj;
-            }
+        Self {
+            text: str,
+            lexer,
+            stack: Vec::new(),
+            state: PullState::Start,
+            peeked: None,
         }
     }
 
-    err
-}
-
-fn parse_arr(cxt: &mut JsonContext, arr_id: u64, tokens: &mut Vec<Token>) -> Option<ParserError> {
-    let mut err: Option<ParserError> = None;
+    //the next event without taking it
+    pub fn peek(&mut self) -> Result<Option<&(Event, Span)>, ParserError> {
+        if self.peeked.is_none() {
+            self.peeked = self.read_event()?;
+        }
 
-    'arr: loop {
-        match tokens.pop() {
-            Some(tkn) => {
-                match tkn.typ {
-                    TokenType::True => {
-                        cxt.array_push(arr_id, cxt.val_bool(true));
-                    }
-                    TokenType::False => {
-                        cxt.array_push(arr_id, cxt.val_bool(false));
-                    }
-                    TokenType::Null => {
-                        cxt.array_push(arr_id, cxt.val_null());
-                    }
-                    TokenType::Number => match parse_num(&tkn.value, tkn.line, tkn.pos) {
-                        Ok(int) => {
-                            if int.2 {
-                                cxt.array_push(arr_id, cxt.val_float(int.1));
-                            } else {
-                                cxt.array_push(arr_id, cxt.val_int(int.0));
-                            }
-                        }
-                        Err(e) => {
-                            err.replace(e);
-                            return err;
-                        }
-                    },
-                    TokenType::String => {
-                        let value = cxt.val_string(tkn.value);
-                        cxt.array_push(arr_id, value);
-                    }
-                    TokenType::OBrk => {
-                        let (nobj, nobj_id) = cxt.val_obj();
-                        cxt.array_push(arr_id, nobj);
-                        match parse_obj(cxt, nobj_id, tokens) {
-                            Some(e) => {
-                                err.replace(e);
-                                return err;
-                            }
-                            None => {}
-                        }
-                    }
-                    TokenType::CSBrk => {
-                        break 'arr;
-                    }
-                    TokenType::OSBrk => {
-                        let (narr, narr_id) = cxt.val_array();
-                        cxt.array_push(arr_id, narr);
-                        match parse_arr(cxt, narr_id, tokens) {
-                            Some(e) => {
-                                err.replace(e);
-                                break 'arr;
-                            }
-                            None => {}
-                        }
-                    }
-                    _ => {
-                        err.replace(ParserError::UnexpectedToken(tkn.value, tkn.line, tkn.pos));
-                        return err;
-                    }
-                }
+        Ok(self.peeked.as_ref())
+    }
 
-                match tokens.pop() {
-                    Some(end_tkn) => {
-                        if end_tkn.typ != TokenType::Comma && end_tkn.typ != TokenType::CSBrk {
-                            err.replace(ParserError::ExpectedChar(',', end_tkn.line, end_tkn.pos));
-                            break 'arr;
-                        } else if end_tkn.typ == TokenType::CSBrk {
-                            break 'arr;
-                        }
-                    }
-                    None => {
-                        err.replace(ParserError::UnexpectedEndOfTokens());
-                        break 'arr;
-                    }
-                }
-            }
-            None => {
-                err.replace(ParserError::UnexpectedEndOfTokens());
-                break 'arr;
-            }
+    //None after the end of the document
+    pub fn next_event(&mut self) -> Result<Option<(Event, Span)>, ParserError> {
+        match self.peeked.take() {
+            Some(event) => Ok(Some(event)),
+            None => self.read_event(),
         }
     }
 
-    err
-}
+    /**
+     * skips the next value with everything in it, a key that is next goes with its value.
+     * an object or array is read event by event without handing them out, so what is in it is
+     * checked like the rest, but strings are not decoded into anything. Ok(None) if the next
+     * event ends an object or array or the document, it is left where it is.
+     */
+    pub fn skip_value(&mut self) -> Result<Option<Span>, ParserError> {
+        self.lexer.skim = true;
+        let skipped = self.skip_events();
+        self.lexer.skim = false;
+        skipped
+    }
 
-fn lexer(p_chars: Chars) -> Result<Vec<Token>, ParserError> {
-    let mut tokens = Vec::<Token>::new();
-    let mut chars = p_chars;
-    let mut pos = Position::new();
-
-    let mut num_pos = Position::new();
-    let mut parse_num = false;
-    let mut num_str = String::new();
-
-    loop {
-        let (failed, ch) = eat_whitespace(&mut chars, &mut pos);
-        if failed {
-            if parse_num {
-                //parse_num = false;
-                tokens.push(Token::new(
-                    num_str.clone(),
-                    TokenType::Number,
-                    num_pos.line,
-                    num_pos.pos,
-                ));
-                // num_str.clear();
-            }
-            break;
+    fn skip_events(&mut self) -> Result<Option<Span>, ParserError> {
+        if let Some((Event::Key(_), _)) = self.peek()? {
+            self.peeked = None;
         }
 
-        if parse_num {
-            if ch.is_alphanumeric() || ch == '.' || ch == '+' || ch == '-' {
-                num_str.push(ch);
-                continue;
-            } else {
-                parse_num = false;
-                tokens.push(Token::new(
-                    num_str.clone(),
-                    TokenType::Number,
-                    num_pos.line,
-                    num_pos.pos,
-                ));
-                num_str.clear();
-            }
-        }
-
-        match ch {
-            '{' => tokens.push(Token::new_ch(ch, TokenType::OBrk, pos.line, pos.pos)),
-            '}' => tokens.push(Token::new_ch(ch, TokenType::CBrk, pos.line, pos.pos)),
-            '[' => tokens.push(Token::new_ch(ch, TokenType::OSBrk, pos.line, pos.pos)),
-            ']' => tokens.push(Token::new_ch(ch, TokenType::CSBrk, pos.line, pos.pos)),
-            ':' => tokens.push(Token::new_ch(ch, TokenType::Colon, pos.line, pos.pos)),
-            ',' => tokens.push(Token::new_ch(ch, TokenType::Comma, pos.line, pos.pos)),
-            't' => {
-                let (ol, op) = (pos.line, pos.pos);
-                let (f0, c0) = eat_whitespace(&mut chars, &mut pos);
-                let (f1, c1) = eat_whitespace(&mut chars, &mut pos);
-                let (f2, c2) = eat_whitespace(&mut chars, &mut pos);
-                if f0 || f1 || f2 || c0 != 'r' || c1 != 'u' || c2 != 'e' {
-                    return Err(ParserError::ExpectedWord(
-                        "true".to_string(),
-                        pos.line,
-                        pos.pos,
-                    ));
-                }
+        let (opens, span) = match self.peek()? {
+            Some((Event::EndObject | Event::EndArray, _)) | None => return Ok(None),
+            Some((event, span)) => (
+                matches!(event, Event::StartObject | Event::StartArray),
+                *span,
+            ),
+        };
+
+        self.peeked = None;
+        if !opens {
+            return Ok(Some(span)); //a scalar, taking it was all
+        }
 
-                tokens.push(Token::new_str("true", TokenType::True, ol, op));
-            }
-            'f' => {
-                let (ol, op) = (pos.line, pos.pos);
-                let (f0, c0) = eat_whitespace(&mut chars, &mut pos);
-                let (f1, c1) = eat_whitespace(&mut chars, &mut pos);
-                let (f2, c2) = eat_whitespace(&mut chars, &mut pos);
-                let (f3, c3) = eat_whitespace(&mut chars, &mut pos);
-                if f0 || f1 || f2 || f3 || c0 != 'a' || c1 != 'l' || c2 != 's' || c3 != 'e' {
-                    return Err(ParserError::ExpectedWord(
-                        "false".to_string(),
-                        pos.line,
-                        pos.pos,
-                    ));
-                }
+        //the start pushed a frame, its end pops it
+        let depth = self.stack.len() - 1;
+        let mut end = span;
+        while self.stack.len() > depth {
+            let Some((_, event_span)) = self.read_event()? else {
+                break;
+            };
+            end = event_span;
+        }
 
-                tokens.push(Token::new_str("false", TokenType::False, ol, op));
-            }
-            'n' => {
-                let (ol, op) = (pos.line, pos.pos);
-                let (f0, c0) = eat_whitespace(&mut chars, &mut pos);
-                let (f1, c1) = eat_whitespace(&mut chars, &mut pos);
-                let (f2, c2) = eat_whitespace(&mut chars, &mut pos);
-                if f0 || f1 || f2 || c0 != 'u' || c1 != 'l' || c2 != 'l' {
-                    return Err(ParserError::ExpectedWord(
-                        "null".to_string(),
-                        pos.line,
-                        pos.pos,
-                    ));
-                }
+        Ok(Some(Span {
+            end: end.end,
+            ..span
+        }))
+    }
 
-                tokens.push(Token::new_str("null", TokenType::Null, ol, op));
-            }
-            '\"' => {
-                let (ol, op) = (pos.line, pos.pos);
-                match parse_string(&mut chars, &mut pos) {
-                    Ok(str) => {
-                        tokens.push(Token::new(str, TokenType::String, ol, op));
-                    }
-                    Err(e) => {
-                        return Err(e);
-                    }
-                }
-            }
-            '/' => {
-                let (failedch, ch) = eat_whitespace(&mut chars, &mut pos);
+    fn after_value(&self) -> PullState {
+        if self.stack.is_empty() {
+            PullState::End
+        } else {
+            PullState::After
+        }
+    }
 
-                if failedch {
-                    return Err(ParserError::UnexpectedEOL(pos.line, pos.pos));
-                }
+    fn read_event(&mut self) -> Result<Option<(Event, Span)>, ParserError> {
+        let result = self.read_event_inner();
+        if result.is_err() {
+            self.state = PullState::Done;
+        }
+        result
+    }
 
-                if ch == '/' {
-                    'comment_loop: loop {
-                        match chars.next() {
-                            Some(char) => {
-                                if char == '\n' {
-                                    break 'comment_loop;
-                                }
-                            }
-                            None => {
-                                break 'comment_loop;
-                            }
-                        }
+    fn read_event_inner(&mut self) -> Result<Option<(Event, Span)>, ParserError> {
+        let lexer = &mut self.lexer;
+
+        loop {
+            match self.state {
+                PullState::Start => {
+                    let tkn = match lexer.next_token()? {
+                        Some(tkn) => tkn,
+                        None => return Err(ParserError::EmptyString(lexer.span_next(0))),
+                    };
+
+                    if matches!(
+                        tkn.typ,
+                        TokenType::CBrk | TokenType::CSBrk | TokenType::Colon | TokenType::Comma
+                    ) {
+                        return Err(ParserError::BadBeginning(lexer.text(&tkn), tkn.span));
                     }
-
-                    pos.line += 1;
-                    pos.pos = 0;
-                } else if ch == '*' {
-                    'comment_loop: loop {
-                        match chars.next() {
-                            Some(char) => {
-                                pos.pos += 1;
-                                if char == '*' {
-                                    match chars.next() {
-                                        Some(char1) => {
-                                            if char1 == '/' {
-                                                break 'comment_loop;
-                                            }
-                                        }
-                                        None => {
-                                            return Err(ParserError::UnexpectedEndOfTokens());
-                                        }
-                                    }
-                                    break 'comment_loop;
-                                } else if char == '\n' {
-                                    pos.line += 1;
-                                    pos.pos = 0;
-                                }
-                            }
-                            None => {
-                                return Err(ParserError::UnexpectedEndOfTokens());
+                    return self.value(tkn).map(Some);
+                }
+                PullState::Item => {
+                    let tkn = lexer.pop()?;
+                    let frame = self.stack.last_mut().unwrap();
+
+                    if tkn.typ == frame.close {
+                        if let Some(comma) = frame.comma {
+                            if !lexer.opts.trailing_commas {
+                                return Err(ParserError::TrailingCommaNotAllowed(comma));
                             }
                         }
+
+                        return Ok(Some(self.end(tkn.span)));
                     }
-                } else {
-                    return Err(ParserError::UnexpectedChar(ch, pos.line, pos.pos));
-                }
-            }
-            _ => {
-                if ch.is_numeric() || ch == '-' || ch == '+' {
-                    num_str.push(ch);
-                    parse_num = true;
-                    num_pos.line = pos.line;
-                    num_pos.pos = pos.pos;
-                } else {
-                    return Err(ParserError::UnexpectedChar(ch, pos.line, pos.pos));
-                }
-            }
-        }
-    }
 
-    Ok(tokens)
-}
+                    if frame.close == TokenType::CSBrk {
+                        if frame.len >= lexer.opts.max_array_length && tkn.typ != TokenType::Comma {
+                            let max = lexer.opts.max_array_length;
+                            return Err(ParserError::ArrayTooLong(max, tkn.span));
+                        }
 
-pub fn parse(str: &String) -> Result<(JsonContext, u64), ParserError> {
-    if str.is_empty() {
-        return Err(ParserError::EmptyString);
-    }
+                        frame.len += 1;
+                        return self.value(tkn).map(Some);
+                    }
 
-    match lexer(str.chars()) {
-        Ok(mut tokens) => {
-            /*for token in tokens.iter() {
-                println!("{}:{} {:?}", token.line, token.pos, token.value);
-            }*/
-            //println!("{tokens:?}");
-            tokens.reverse();
-            let token = tokens.pop().unwrap();
+                    if !is_key(&tkn, lexer)? {
+                        return Err(unexpected(tkn, lexer));
+                    }
 
-            if token.typ == TokenType::OBrk {
-                let (mut cxt, root_id) = JsonContext::new(true);
+                    let span = tkn.span;
+                    let skimmed = lexer.skim.then(|| skimmed_key(self.text, &tkn, lexer.opts));
+                    let key = lexer.take_text(tkn);
+                    if let Some(keys) = &mut frame.keys {
+                        let seen = skimmed.unwrap_or_else(|| Cow::Owned(key.clone()));
+                        if keys.contains(&seen) {
+                            return Err(ParserError::KeyExists(seen.into_owned(), span));
+                        }
+                        keys.insert(seen);
+                    }
 
-                match parse_obj(&mut cxt, root_id, &mut tokens) {
-                    Some(e) => {
-                        return Err(e);
+                    if frame.len >= lexer.opts.max_object_members {
+                        let max = lexer.opts.max_object_members;
+                        return Err(ParserError::TooManyMembers(max, span));
                     }
-                    None => {}
-                }
 
-                if !tokens.is_empty() {
-                    let tkn_end = tokens.pop().unwrap();
-                    return Err(ParserError::UnexpectedToken(
-                        tkn_end.value,
-                        tkn_end.line,
-                        tkn_end.pos,
-                    ));
+                    frame.len += 1;
+                    self.state = PullState::Value;
+                    return Ok(Some((Event::Key(key), span)));
                 }
-
-                return Ok((cxt, root_id));
-            } else if token.typ == TokenType::OSBrk {
-                let (mut cxt, root_id) = JsonContext::new(false);
-
-                match parse_arr(&mut cxt, root_id, &mut tokens) {
-                    Some(e) => {
-                        return Err(e);
+                PullState::Value => {
+                    lexer.expect(TokenType::Colon)?;
+                    let tkn = lexer.pop()?;
+                    return self.value(tkn).map(Some);
+                }
+                PullState::After => {
+                    let tkn = lexer.pop()?;
+                    let close = self.stack.last().unwrap().close;
+
+                    match tkn.typ {
+                        TokenType::Comma => {
+                            self.stack.last_mut().unwrap().comma = Some(tkn.span);
+                            self.state = PullState::Item;
+                        }
+                        typ if typ == close => return Ok(Some(self.end(tkn.span))),
+                        _ => {
+                            //the end of an enclosing object or array, or anything else
+                            let enclosing = self.stack.iter().any(|f| f.close == tkn.typ);
+                            let ch = match (enclosing, close) {
+                                (false, _) => ',',
+                                (true, TokenType::CBrk) => '}',
+                                (true, _) => ']',
+                            };
+                            return Err(ParserError::ExpectedChar(ch, tkn.span));
+                        }
                     }
-                    None => {}
                 }
+                PullState::End => {
+                    if let Some(tkn_end) = lexer.next_token()? {
+                        return Err(ParserError::UnexpectedToken(
+                            lexer.text(&tkn_end),
+                            tkn_end.span,
+                        ));
+                    }
 
-                if !tokens.is_empty() {
-                    let tkn_end = tokens.pop().unwrap();
-                    return Err(ParserError::UnexpectedToken(
-                        tkn_end.value,
-                        tkn_end.line,
-                        tkn_end.pos,
-                    ));
+                    self.state = PullState::Done;
+                    return Ok(None);
                 }
-                return Ok((cxt, root_id));
-            } else {
-                return Err(ParserError::BadBeginning(
-                    token.value,
-                    token.line,
-                    token.pos,
-                ));
+                PullState::Done => return Ok(None),
             }
         }
-        Err(e) => {
-            return Err(e);
+    }
+
+    //a value starting with tkn, a `{` or `[` is pushed on the stack
+    fn value(&mut self, tkn: Token) -> Result<(Event, Span), ParserError> {
+        let lexer = &mut self.lexer;
+        let span = tkn.span;
+
+        let close = match tkn.typ {
+            TokenType::OBrk => TokenType::CBrk,
+            TokenType::OSBrk => TokenType::CSBrk,
+            _ => {
+                let event = match read_scalar(tkn, lexer)? {
+                    Scalar::Bool(bool) => Event::Bool(bool),
+                    Scalar::Null => Event::Null,
+                    Scalar::Number(Number::Int(int)) => Event::Int(int),
+                    Scalar::Number(Number::UInt(uint)) => Event::UInt(uint),
+                    Scalar::Number(Number::Float(float)) => Event::Float(float),
+                    Scalar::Text(text) => Event::Number(text),
+                    Scalar::String(str) => Event::String(str),
+                };
+
+                self.state = self.after_value();
+                return Ok((event, span));
+            }
+        };
+
+        if self.stack.len() >= lexer.opts.max_depth {
+            return Err(ParserError::DepthLimitExceeded(lexer.opts.max_depth, span));
         }
+
+        let object = close == TokenType::CBrk;
+        let keys = (object && lexer.opts.duplicate_keys == DuplicateKeys::Error).then(HashSet::new);
+        self.stack.push(PullFrame {
+            close,
+            comma: None,
+            len: 0,
+            keys,
+        });
+        self.state = PullState::Item;
+
+        let event = if object {
+            Event::StartObject
+        } else {
+            Event::StartArray
+        };
+        Ok((event, span))
+    }
+
+    //the end of the innermost object or array
+    fn end(&mut self, span: Span) -> (Event, Span) {
+        let frame = self.stack.pop().unwrap();
+        self.state = self.after_value();
+
+        let event = if frame.close == TokenType::CBrk {
+            Event::EndObject
+        } else {
+            Event::EndArray
+        };
+        (event, span)
     }
 }